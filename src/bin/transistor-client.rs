@@ -15,12 +15,25 @@ fn main() -> Result<(), Error> {
     }
 
     let server = &args[1];
+    let displays = display_source(&args[2..])?;
 
     println!("[INF] transistor client startup! server: {}", server);
-    print_displays();
+    print_displays(displays.as_ref());
 
-    let mut client = Client::new(server, 1.0)?;
-    client.start()?;
+    let pairing = match args.iter().any(|x| x == "--pair") {
+        true => Some(read_pairing_code()?),
+        false => None,
+    };
+
+    let mut client = Client::new(server, displays.as_ref())?;
+    client.pairing = pairing;
+
+    // declared displays may not exist at all; nothing to inject into then
+    if args.iter().any(|x| x == "--displays" || x == "--virtual") {
+        client.start_with(&mut LogSink::default())?;
+    } else {
+        client.start()?;
+    }
 
     Ok(())
 }
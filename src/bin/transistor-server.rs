@@ -1,3 +1,4 @@
+use std::env;
 use std::io::Error;
 
 use transistor::*;
@@ -5,10 +6,17 @@ use transistor::*;
 fn main() -> Result<(), Error> {
     println!("[INF] transistor server startup!");
 
-    print_displays();
+    let args: Vec<String> = env::args().collect();
+    let displays = display_source(&args[1..])?;
+
+    print_displays(displays.as_ref());
 
     let client_config = config_dir!("server").join("authorized_clients.json");
-    let server = Server::new(1.0)?;
+    let server = Server::new(displays.as_ref())?;
+
+    if args.iter().any(|x| x == "--pair") {
+        server.pair();
+    }
 
     server.start(client_config);
 
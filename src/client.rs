@@ -1,11 +1,11 @@
-use std::collections::HashMap;
 use std::fs;
-use std::io::{stdout, Error, ErrorKind::*, Write};
-use std::mem;
-use std::net::TcpStream;
+use std::io::{stdin, stdout, Error, ErrorKind::*, Write};
+use std::thread;
+use std::time::Duration;
 
 use bincode::deserialize;
-use display_info::DisplayInfo;
+use ring::rand::SystemRandom;
+use ring::signature::{Ed25519KeyPair, KeyPair};
 use serde::{Deserialize, Serialize};
 
 use crate::comm::*;
@@ -14,48 +14,85 @@ use crate::*;
 
 pub type Cid = u32;
 
+/* how long the client waits for the server before looking at the cursor itself */
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct AuthorizedClient {
     pub cid: Cid,
-    // may have key-based auth in future
+    #[serde(default)]
+    pub key: Option<String>, // ed25519 public key in hex; clients without one cannot connect
 }
 
 #[derive(Debug)]
 pub struct Client {
-    pub tcp: TcpStream,
+    pub tcp: SecureStream,
     pub cid: Cid,
     pub displays: Vec<Display>,
+    pub capabilities: Vec<Capability>, // negotiated with the peer
+    pub pairing: Option<String>,       // code to pair with; see read_pairing_code()
 }
 
 impl Client {
-    pub fn new(server: &str, display_scale: f32) -> Result<Client, Error> {
+    pub fn new(server: &str, source: &dyn DisplaySource) -> Result<Client, Error> {
         // mkdir -p
         fs::create_dir_all(config_dir!("client"))?;
 
         let cid = load_or_generate_cid()?;
+        let key = load_or_generate_key()?;
+
+        // to be added to authorized_clients.json on the server
+        println!(
+            "[INF] cid: {}, public key: {}",
+            cid,
+            hex::encode(key.public_key())
+        );
+        println!(
+            "[INF] key fingerprint: {}",
+            fingerprint(key.public_key().as_ref())
+        );
 
         Ok(Client {
-            tcp: TcpStream::connect(server)?,
+            tcp: SecureStream::connect(server)?,
             cid,
-            displays: DisplayInfo::all()
-                .expect("[ERR] failed to get system displays")
-                .into_iter()
-                .map(|x| Display::from(x, cid, display_scale))
-                .collect(),
+            displays: source.displays(cid)?,
+            capabilities: CLIENT_CAPABILITIES.to_vec(),
+            pairing: None,
         })
     }
 
     pub fn start(&mut self) -> Result<(), Error> {
-        // transmit cid to server
-        if let Err(e) = tcp_write(&mut self.tcp, self.cid) {
+        self.start_with(&mut SystemSink::new()?)
+    }
+
+    /* handshake and apply received messages to the given sink */
+    pub fn start_with<S: InputSink>(&mut self, sink: &mut S) -> Result<(), Error> {
+        let key = load_or_generate_key()?;
+        let public = hex::encode(key.public_key());
+
+        let hello = Hello {
+            version: PROTOCOL_VERSION,
+            cid: self.cid,
+            capabilities: CLIENT_CAPABILITIES.to_vec(),
+            nonce: rand::random(),
+            pairing: self.pairing.take().map(|code| PairRequest {
+                code,
+                key: key.public_key().as_ref().to_vec(),
+            }),
+        };
+        let pairing = hello.pairing.is_some();
+        let nonce = hello.nonce;
+
+        // introduce ourselves to the server
+        if let Err(e) = tcp_write(&mut self.tcp, hello) {
             return Err(Error::new(
                 ConnectionRefused,
                 format!("handshake failed: {:?}", e),
             ));
         };
 
-        /* receive display counts; 0 is unauthorized */
-        let mut buffer = vec![0u8; mem::size_of::<u32>()];
+        /* the server accepts or refuses the hello */
+        let mut buffer = Vec::new();
 
         if let Err(e) = tcp_read(&mut self.tcp, &mut buffer) {
             return Err(Error::new(
@@ -64,10 +101,45 @@ impl Client {
             ));
         };
 
-        let disp_cnt: u32 = deserialize(&buffer).unwrap();
+        /* prove we own the cid */
+        let challenge = match deserialize(&buffer) {
+            Ok(HandshakeStatus::HandshakeChallenge(challenge)) => challenge,
+            Ok(HandshakeStatus::HandshakeErr(reason)) => return Err(version_refused(reason)),
+            Ok(HandshakeStatus::HandshakeRejected(reason)) => {
+                return Err(rejected(reason, &public))
+            }
+            Ok(HandshakeStatus::HandshakeOk) | Err(_) => return Err(incompatible_server()),
+        };
+        let binding = self.tcp.binding()?;
+        let payload = challenge_payload(self.cid, &nonce, &challenge.nonce, &binding);
+
+        let response = Response {
+            signature: key.sign(&payload).as_ref().to_vec(),
+        };
 
-        if disp_cnt < 1 {
-            return Err(Error::new(ConnectionRefused, "authorization failed"));
+        if let Err(e) = tcp_write(&mut self.tcp, response) {
+            return Err(Error::new(
+                ConnectionRefused,
+                format!("handshake failed: {:?}", e),
+            ));
+        };
+
+        if let Err(e) = tcp_read(&mut self.tcp, &mut buffer) {
+            return Err(Error::new(
+                ConnectionRefused,
+                format!("handshake failed: {:?}", e),
+            ));
+        };
+
+        match deserialize(&buffer) {
+            Ok(HandshakeStatus::HandshakeOk) => {}
+            Ok(HandshakeStatus::HandshakeErr(reason)) => return Err(version_refused(reason)),
+            Ok(HandshakeStatus::HandshakeRejected(reason)) => {
+                return Err(rejected(reason, &public))
+            }
+            Ok(HandshakeStatus::HandshakeChallenge(_)) | Err(_) => {
+                return Err(incompatible_server())
+            }
         }
 
         // receive server's current display configurations
@@ -78,8 +150,25 @@ impl Client {
             ));
         };
 
-        let server_disp_map: HashMap<Did, Display> = deserialize(&buffer).unwrap();
-        let server_disp: Vec<Display> = server_disp_map.values().cloned().collect();
+        match deserialize::<u32>(&buffer) {
+            Ok(PROTOCOL_VERSION) => {}
+            Ok(version) => {
+                return Err(Error::new(
+                    ConnectionRefused,
+                    format!(
+                        "[ERR] server speaks protocol version {}, we speak version {}",
+                        version, PROTOCOL_VERSION
+                    ),
+                ));
+            }
+            Err(_) => return Err(incompatible_server()),
+        }
+
+        let welcome: Welcome = deserialize(&buffer).map_err(|_| incompatible_server())?;
+
+        self.capabilities = welcome.capabilities;
+
+        let server_disp: Vec<Display> = welcome.displays.into_values().collect();
 
         /* configure our displays' attach position and transmit to server */
         self.set_display_position(server_disp);
@@ -99,22 +188,100 @@ impl Client {
             ));
         };
 
-        if let HandshakeStatus::HandshakeErr = deserialize(&buffer).unwrap() {
-            return Err(Error::new(ConnectionRefused, "[ERR] request rejected"));
-        };
+        match deserialize(&buffer) {
+            Ok(HandshakeStatus::HandshakeOk) => {}
+            Ok(HandshakeStatus::HandshakeErr(reason)) => return Err(version_refused(reason)),
+            Ok(HandshakeStatus::HandshakeRejected(reason)) => {
+                return Err(rejected(reason, &public))
+            }
+            Ok(HandshakeStatus::HandshakeChallenge(_)) | Err(_) => {
+                return Err(incompatible_server())
+            }
+        }
+
+        if pairing {
+            println!("[INF] paired with the server");
+        }
 
         println!("[INF] connected!");
 
+        // the cursor lock can be toggled from here as well
+        match self.tcp.try_clone() {
+            Ok(tcp) => {
+                thread::spawn(move || control(tcp));
+            }
+            Err(e) => eprintln!("[WRN] cursor lock control unavailable: {}", e),
+        }
+
+        // edges of our displays that lead to the other machines; the server tells which
+        let mut layout = self.displays.clone();
+
+        // the cursor may be moved here while the server has nothing to say
+        self.tcp.set_read_timeout(Some(POLL_INTERVAL))?;
+
+        // where the server put the cursor last; anywhere else it was moved on this machine
+        let mut placed = None;
+        let mut held = HeldInput::default();
+
         loop {
-            if let Err(e) = tcp_read(&mut self.tcp, &mut buffer) {
-                return Err(Error::new(
-                    UnexpectedEof,
-                    format!("warp in failed: {:?}", e),
-                ));
+            let received = match tcp_poll(&mut self.tcp, &mut buffer) {
+                Ok(received) => received,
+                Err(e) => {
+                    release_all(sink, &mut held);
+
+                    return Err(Error::new(
+                        UnexpectedEof,
+                        format!("warp in failed: {:?}", e),
+                    ));
+                }
             };
 
-            let msg: Message = deserialize(&buffer).unwrap();
-            println!("[DBG] msg: {:?}", msg);
+            if received.is_none() {
+                if let Err(e) = report_exit(&mut self.tcp, sink, &layout, &mut placed) {
+                    eprintln!("[ERR] warp out failed: {}", e);
+                }
+
+                continue;
+            }
+
+            // a server that sends garbage cannot be trusted with the rest of the stream either
+            let msg: Message = match deserialize(&buffer) {
+                Ok(msg) => msg,
+                Err(e) => {
+                    release_all(sink, &mut held);
+
+                    return Err(Error::new(
+                        InvalidData,
+                        format!("invalid message from the server: {}", e),
+                    ));
+                }
+            };
+
+            if let Action::Lock(locked) = msg.action {
+                if locked {
+                    println!("[INF] cursor locked to the current display");
+                } else {
+                    println!("[INF] cursor unlocked");
+                }
+
+                continue;
+            }
+
+            if let Action::Exits(zones) = msg.action {
+                set_exits(&mut layout, msg.disp, zones);
+                continue;
+            }
+
+            let moved = matches!(msg.action, Action::Warp | Action::Move);
+            held.update(&msg.action);
+
+            if let Err(e) = inject(sink, msg) {
+                eprintln!("[ERR] input injection failed: {}", e);
+            }
+
+            if moved {
+                placed = sink.position().ok();
+            }
         }
     }
 
@@ -170,11 +337,220 @@ impl Client {
             }
         } else {
             // config not exists
-            return prompt_display_position(displays, server_conf);
+            prompt_display_position(displays, server_conf)
         }
     }
 }
 
+/* apply a server message to the sink */
+pub fn inject<S: InputSink>(sink: &mut S, msg: Message) -> Result<(), Error> {
+    match msg.action {
+        Action::Warp => sink.warp(msg.x, msg.y),
+        Action::Move => sink.motion(msg.x, msg.y),
+        Action::KeyDown(input) => sink.key(input, true),
+        Action::KeyUp(input) => sink.key(input, false),
+        Action::ButtonDown(button) => sink.button(button, true),
+        Action::ButtonUp(button) => sink.button(button, false),
+        Action::Scroll(delta) => sink.scroll(delta),
+        Action::Lock(_) | Action::Leave(_) | Action::Exits(_) => Ok(()), // not an input
+    }
+}
+
+/* the server is gone; let go of what it left pressed */
+pub fn release_all<S: InputSink>(sink: &mut S, held: &mut HeldInput) {
+    for action in held.release() {
+        let msg = Message {
+            disp: 0,
+            action,
+            x: 0,
+            y: 0,
+        };
+
+        if let Err(e) = inject(sink, msg) {
+            eprintln!("[ERR] input release failed: {}", e);
+        }
+    }
+}
+
+/* the ways out of display `disp` as the server laid them out; links and wraps included */
+pub fn set_exits(layout: &mut [Display], disp: Did, zones: Vec<WarpZone>) {
+    match layout.iter_mut().find(|x| x.id == disp) {
+        Some(disp) => disp.warpzones = zones,
+        None => eprintln!("[WRN] exits for unknown display {}", disp),
+    }
+}
+
+/* Leave message for the edge the cursor stands on, if that edge leads to another machine */
+pub fn find_exit<S: InputSink>(sink: &S, layout: &[Display]) -> Result<Option<Message>, Error> {
+    let (x, y) = sink.position()?;
+
+    for disp in layout {
+        let (gx, gy) = disp.system_to_global(x, y);
+
+        if !disp.contains(gx, gy) {
+            continue;
+        }
+
+        // the server decides where the cursor goes; any edge with a way out will do here
+        let exit = disp
+            .find_warpzone(gx, gy, (0, 0), &Crossing::default())
+            .map(|wz| Message {
+                disp: disp.id,
+                action: Action::Leave(wz.direction),
+                x,
+                y,
+            });
+
+        return Ok(exit);
+    }
+
+    Ok(None)
+}
+
+/* tell the server when the cursor was moved off our displays on this machine */
+fn report_exit<S: InputSink>(
+    tcp: &mut SecureStream,
+    sink: &S,
+    layout: &[Display],
+    placed: &mut Option<(i32, i32)>,
+) -> Result<(), Error> {
+    let position = sink.position()?;
+
+    // the cursor is not ours or stays where the server put it
+    match *placed {
+        Some(x) if x != position => *placed = Some(position),
+        _ => return Ok(()),
+    }
+
+    if let Some(msg) = find_exit(sink, layout)? {
+        tcp_write(tcp, msg)?;
+
+        // the server takes over until it puts the cursor here again
+        *placed = None;
+    }
+
+    Ok(())
+}
+
+/* the code the server shows in pairing mode; asked before connecting so the server never waits on us */
+pub fn read_pairing_code() -> Result<String, Error> {
+    print!("[INF] enter the pairing code shown on the server: ");
+    stdout().flush()?;
+
+    let mut code = String::new();
+    stdin().read_line(&mut code)?;
+
+    Ok(code.trim().to_string())
+}
+
+/* `lock` and `unlock` typed on stdin go to the server */
+fn control(mut tcp: SecureStream) {
+    println!("[INF] enter `lock` or `unlock` to hold the cursor on its current display");
+
+    loop {
+        let mut input = String::new();
+
+        // no terminal attached
+        if !matches!(stdin().read_line(&mut input), Ok(n) if n > 0) {
+            return;
+        }
+
+        let locked = match input.trim() {
+            "lock" => true,
+            "unlock" => false,
+            "" => continue,
+            _ => {
+                eprintln!("  [ERR] invalid input");
+                continue;
+            }
+        };
+
+        let msg = Message {
+            disp: 0,
+            action: Action::Lock(locked),
+            x: 0,
+            y: 0,
+        };
+
+        if let Err(e) = tcp_write(&mut tcp, msg) {
+            eprintln!("[ERR] cursor lock request failed: {}", e);
+            return;
+        }
+    }
+}
+
+/* what the user can do about a refusal */
+fn rejected(reason: Rejection, key: &str) -> Error {
+    let (kind, advice) = match &reason {
+        Rejection::Unauthorized(cid) => (
+            PermissionDenied,
+            format!(
+                "pair with --pair, or add {{ \"cid\": {}, \"key\": \"{}\" }} to authorized_clients.json on the server",
+                cid, key
+            ),
+        ),
+        Rejection::DuplicateCid(_) => (
+            AlreadyExists,
+            format!(
+                "remove {} on one of the machines to get a new cid",
+                config_dir!("client").join("cid.txt").display()
+            ),
+        ),
+        Rejection::Overlap(_) => (
+            InvalidInput,
+            format!(
+                "fix the display positions in {}",
+                config_dir!("client").join("client_config.json").display()
+            ),
+        ),
+        Rejection::Isolated(_) => (
+            InvalidInput,
+            "attach the displays next to another display or link them in server_config.json"
+                .to_string(),
+        ),
+        Rejection::ServerFull(_) => (
+            ConnectionRefused,
+            "try again once another client disconnects".to_string(),
+        ),
+        Rejection::Malformed(_) => (InvalidData, "check the client displays".to_string()),
+        Rejection::PairingClosed => (
+            PermissionDenied,
+            "start the server with --pair and try again".to_string(),
+        ),
+        Rejection::WrongCode => (
+            PermissionDenied,
+            "check the code shown on the server and try again".to_string(),
+        ),
+        Rejection::Declined => (
+            PermissionDenied,
+            "start the server with --pair again and accept the client there".to_string(),
+        ),
+    };
+
+    Error::new(
+        kind,
+        format!("[ERR] request rejected: {}; {}", reason, advice),
+    )
+}
+
+/* the server speaks another protocol version */
+fn version_refused(reason: String) -> Error {
+    Error::new(
+        Unsupported,
+        format!(
+            "[ERR] request rejected: {}; run the same transistor version on the server and the client",
+            reason
+        ),
+    )
+}
+
+fn incompatible_server() -> Error {
+    Error::new(
+        InvalidData,
+        "[ERR] unexpected reply from server; it may run an incompatible version",
+    )
+}
+
 fn load_or_generate_cid() -> Result<Cid, Error> {
     let cid_file = config_dir!("client").join("cid.txt");
 
@@ -191,14 +567,41 @@ fn load_or_generate_cid() -> Result<Cid, Error> {
     }
 }
 
+/* ed25519 keypair next to cid.txt; the public half goes into the server's authorized_clients.json */
+fn load_or_generate_key() -> Result<Ed25519KeyPair, Error> {
+    let key_file = config_dir!("client").join("client_key.der");
+
+    if !key_file.exists() {
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new())
+            .map_err(|_| Error::new(Other, "key generation failed"))?;
+
+        // the private key never leaves this machine
+        write_private(&key_file, pkcs8.as_ref())?;
+    }
+
+    let pkcs8 = fs::read(&key_file)?;
+
+    Ed25519KeyPair::from_pkcs8(&pkcs8).map_err(|_| {
+        Error::new(
+            InvalidData,
+            format!(
+                "invalid client key {}",
+                key_file.as_os_str().to_str().unwrap()
+            ),
+        )
+    })
+}
+
 fn prompt_display_position(displays: &mut Vec<Display>, server_conf: Vec<Display>) {
     println!("########## display setup ##########");
     println!("[INF] current server displays:");
 
     for (i, d) in server_conf.iter().enumerate() {
+        let (width, height) = d.size();
+
         println!(
             "  [{:2}] x: {:4}, y: {:4}, width: {:4}, height: {:4}",
-            i, d.x, d.y, d.width, d.height
+            i, d.x, d.y, width, height
         );
     }
 
@@ -210,9 +613,11 @@ fn prompt_display_position(displays: &mut Vec<Display>, server_conf: Vec<Display
 
     while i < tot {
         let d = displays.get_mut(i).unwrap();
+        let (width, height) = d.size();
+
         println!(
             "[{:2}/{}] {} - width: {:4}, height: {:4}",
-            i, tot, d.name, d.width, d.height
+            i, tot, d.name, width, height
         );
 
         print!("  x coordinate: ");
@@ -241,7 +646,7 @@ fn prompt_display_position(displays: &mut Vec<Display>, server_conf: Vec<Display
         loop {
             print!(
                 "[CONFIRM] {} - x: {}, y: {}, width: {:4}, height: {:4} [y/n/p]: ",
-                d.name, d.x, d.y, d.width, d.height
+                d.name, d.x, d.y, width, height
             );
             stdout().flush().unwrap();
 
@@ -261,9 +666,7 @@ fn prompt_display_position(displays: &mut Vec<Display>, server_conf: Vec<Display
                     break; // current display again
                 }
                 'p' => {
-                    if i > 0 {
-                        i -= 1; // previous display
-                    }
+                    i = i.saturating_sub(1);
                     break;
                 }
                 _ => {
@@ -318,3 +721,113 @@ fn prompt_display_position(displays: &mut Vec<Display>, server_conf: Vec<Display
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(action: Action) -> Message {
+        Message {
+            disp: 1,
+            action,
+            x: 0,
+            y: 0,
+        }
+    }
+
+    fn injected(actions: Vec<Action>) -> Vec<SinkEvent> {
+        let mut sink = RecordingSink::default();
+
+        for action in actions {
+            inject(&mut sink, message(action)).unwrap();
+        }
+
+        sink.events
+    }
+
+    #[test]
+    fn keys_are_injected() {
+        let input = KeyInput {
+            key: Key::A,
+            scan: 30,
+            modifiers: Modifiers::default(),
+        };
+
+        assert_eq!(
+            injected(vec![Action::KeyDown(input), Action::KeyUp(input)]),
+            vec![SinkEvent::Key(Key::A, true), SinkEvent::Key(Key::A, false)]
+        );
+    }
+
+    #[test]
+    fn buttons_are_injected() {
+        assert_eq!(
+            injected(vec![
+                Action::ButtonDown(Button::Right),
+                Action::ButtonUp(Button::Right),
+                Action::ButtonDown(Button::Back),
+            ]),
+            vec![
+                SinkEvent::Button(Button::Right, true),
+                SinkEvent::Button(Button::Right, false),
+                SinkEvent::Button(Button::Back, true),
+            ]
+        );
+    }
+
+    #[test]
+    fn scroll_keeps_fractions() {
+        let delta = ScrollDelta { x: 0.25, y: -1.5 };
+
+        assert_eq!(
+            injected(vec![Action::Scroll(delta)]),
+            vec![SinkEvent::Scroll(delta)]
+        );
+    }
+
+    #[test]
+    fn control_messages_are_not_injected() {
+        assert!(injected(vec![
+            Action::Lock(true),
+            Action::Leave(ZoneDirection::HorizontalLeft),
+        ])
+        .is_empty());
+    }
+
+    #[test]
+    fn held_input_is_released_when_the_server_is_gone() {
+        let mut sink = RecordingSink::default();
+        let mut held = HeldInput::default();
+        let input = |key| KeyInput {
+            key,
+            scan: 0,
+            modifiers: Modifiers::default(),
+        };
+
+        for action in [
+            Action::KeyDown(input(Key::LControl)),
+            Action::KeyDown(input(Key::A)),
+            Action::KeyUp(input(Key::A)),
+            Action::ButtonDown(Button::Left),
+        ] {
+            held.update(&action);
+            inject(&mut sink, message(action)).unwrap();
+        }
+
+        sink.events.clear();
+        release_all(&mut sink, &mut held);
+
+        assert_eq!(
+            sink.events,
+            vec![
+                SinkEvent::Key(Key::LControl, false),
+                SinkEvent::Button(Button::Left, false),
+            ]
+        );
+
+        // once only
+        sink.events.clear();
+        release_all(&mut sink, &mut held);
+        assert!(sink.events.is_empty());
+    }
+}
@@ -1,11 +1,141 @@
+use std::collections::{HashMap, HashSet};
+
 use serde::{Deserialize, Serialize};
 
 use crate::display::*;
+use crate::input::*;
+use crate::Cid;
+
+/* bump on every change to the handshake or to anything sent over the wire */
+pub const PROTOCOL_VERSION: u32 = 10;
+
+/* features a peer may or may not support */
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Capability {
+    Pointer,
+    Keyboard,
+    Scroll,
+    Lock,
+    Clipboard,
+    FileTransfer,
+}
+
+/* what this build supports on either side */
+pub const CAPABILITIES: &[Capability] = &[
+    Capability::Pointer,
+    Capability::Keyboard,
+    Capability::Scroll,
+    Capability::Lock,
+];
+
+/* what a client of this build can take; keys are injected through XTest on linux only */
+pub const CLIENT_CAPABILITIES: &[Capability] = &[
+    Capability::Pointer,
+    #[cfg(target_os = "linux")]
+    Capability::Keyboard,
+    Capability::Scroll,
+    Capability::Lock,
+];
+
+/* capabilities both peers support */
+pub fn negotiate(theirs: &[Capability]) -> Vec<Capability> {
+    CAPABILITIES
+        .iter()
+        .filter(|x| theirs.contains(x))
+        .copied()
+        .collect()
+}
+
+pub type Nonce = [u8; 32];
+
+/* secret both ends of a TLS session derive on their own; see SecureStream::binding */
+pub type Binding = [u8; 32];
+
+/* first message from the client; `version` stays the first field in every version */
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Hello {
+    pub version: u32,
+    pub cid: Cid,
+    pub capabilities: Vec<Capability>,
+    pub nonce: Nonce,
+    pub pairing: Option<PairRequest>, // unknown clients only
+}
+
+/* pairing code shown by the server and the key to authorize with it */
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PairRequest {
+    pub code: String,
+    pub key: Vec<u8>,
+}
+
+/* the client proves it holds the private key of its cid by signing this */
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Challenge {
+    pub nonce: Nonce,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Response {
+    pub signature: Vec<u8>, // ed25519 over `challenge_payload`
+}
+
+/*
+ * fresh nonces from both sides make a signature good for a single handshake only;
+ * the binding of the TLS session keeps it from being relayed into another session
+ */
+pub fn challenge_payload(cid: Cid, client: &Nonce, server: &Nonce, binding: &Binding) -> Vec<u8> {
+    let mut payload = b"transistor client auth".to_vec();
+    payload.extend(cid.to_be_bytes());
+    payload.extend(client);
+    payload.extend(server);
+    payload.extend(binding);
+    payload
+}
+
+/* server's answer to an accepted `Hello`; `version` stays the first field in every version */
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Welcome {
+    pub version: u32,
+    pub capabilities: Vec<Capability>, // negotiated
+    pub displays: HashMap<Did, Display>,
+}
 
 #[derive(Serialize, Deserialize, Debug)]
 pub enum Action {
+    // (x, y) in the OS coordinates of the receiving machine
     Warp,
+    // (x, y) relative motion
     Move,
+    KeyDown(KeyInput),
+    KeyUp(KeyInput),
+    ButtonDown(Button),
+    ButtonUp(Button),
+    Scroll(ScrollDelta),
+    // no switching between displays while true; sent both ways
+    Lock(bool),
+    // the cursor left display `disp` through this edge on its own; client to server,
+    // (x, y) in the OS coordinates of the sending machine
+    Leave(ZoneDirection),
+    // the ways out of display `disp` as the server laid them out; server to client,
+    // sent whenever the layout changes
+    Exits(Vec<WarpZone>),
+}
+
+impl Action {
+    /* what the receiving side needs to handle the action */
+    pub fn capability(&self) -> Capability {
+        match self {
+            Action::Warp
+            | Action::Move
+            | Action::ButtonDown(_)
+            | Action::ButtonUp(_)
+            | Action::Leave(_)
+            | Action::Exits(_) => Capability::Pointer,
+            Action::KeyDown(_) | Action::KeyUp(_) => Capability::Keyboard,
+            Action::Scroll(_) => Capability::Scroll,
+            Action::Lock(_) => Capability::Lock,
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -16,3 +146,90 @@ pub struct Message {
     pub y: i32,
 }
 
+/* keys and buttons pressed on a machine and not released yet */
+#[derive(Debug, Default)]
+pub struct HeldInput {
+    keys: HashMap<Key, KeyInput>,
+    buttons: HashSet<Button>,
+}
+
+impl HeldInput {
+    pub fn update(&mut self, action: &Action) {
+        match action {
+            Action::KeyDown(input) => {
+                self.keys.insert(input.key, *input);
+            }
+            Action::KeyUp(input) => {
+                self.keys.remove(&input.key);
+            }
+            Action::ButtonDown(button) => {
+                self.buttons.insert(*button);
+            }
+            Action::ButtonUp(button) => {
+                self.buttons.remove(button);
+            }
+            _ => {}
+        }
+    }
+
+    /// releases for everything held; nothing is held afterwards
+    pub fn release(&mut self) -> Vec<Action> {
+        let keys = self.keys.drain().map(|(_, input)| Action::KeyUp(input));
+        let buttons = self.buttons.drain().map(Action::ButtonUp);
+
+        keys.chain(buttons).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn payload_covers_every_input() {
+        let payload = challenge_payload(1, &[1; 32], &[2; 32], &[3; 32]);
+
+        assert_ne!(payload, challenge_payload(2, &[1; 32], &[2; 32], &[3; 32]));
+        assert_ne!(payload, challenge_payload(1, &[9; 32], &[2; 32], &[3; 32]));
+        assert_ne!(payload, challenge_payload(1, &[1; 32], &[9; 32], &[3; 32]));
+        assert_ne!(payload, challenge_payload(1, &[1; 32], &[2; 32], &[9; 32]));
+    }
+
+    #[test]
+    fn payload_does_not_mix_up_the_nonces() {
+        let payload = challenge_payload(1, &[1; 32], &[2; 32], &[3; 32]);
+
+        assert_ne!(payload, challenge_payload(1, &[2; 32], &[1; 32], &[3; 32]));
+    }
+
+    #[test]
+    fn negotiation_keeps_what_both_sides_have() {
+        let theirs = [
+            Capability::Scroll,
+            Capability::Clipboard,
+            Capability::Pointer,
+        ];
+
+        // ours in our order; what we lack is dropped
+        assert_eq!(
+            negotiate(&theirs),
+            vec![Capability::Pointer, Capability::Scroll]
+        );
+        assert_eq!(negotiate(CAPABILITIES), CAPABILITIES.to_vec());
+    }
+
+    #[test]
+    fn negotiation_without_capabilities_leaves_none() {
+        assert!(negotiate(&[]).is_empty());
+        assert!(negotiate(&[Capability::FileTransfer]).is_empty());
+    }
+
+    #[test]
+    fn clients_offer_keys_only_where_they_can_inject_them() {
+        assert!(CLIENT_CAPABILITIES.iter().all(|x| CAPABILITIES.contains(x)));
+        assert_eq!(
+            CLIENT_CAPABILITIES.contains(&Capability::Keyboard),
+            cfg!(target_os = "linux")
+        );
+    }
+}
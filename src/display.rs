@@ -1,19 +1,25 @@
 use std::collections::HashMap;
+use std::fmt;
+use std::fs;
 use std::io::{Error, ErrorKind::*};
-use std::sync::{Arc, RwLock};
+use std::path::PathBuf;
 
 use display_info::DisplayInfo;
 use serde::{Deserialize, Serialize};
 
-use crate::Cid;
+use crate::{Cid, MARGIN, SERVER_CID};
 
 pub type Did = u32;
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ZoneDirection {
+    #[serde(rename = "left")]
     HorizontalLeft,
+    #[serde(rename = "right")]
     HorizontalRight,
+    #[serde(rename = "top")]
     VerticalUp,
+    #[serde(rename = "bottom")]
     VerticalDown,
 }
 
@@ -28,30 +34,128 @@ impl ZoneDirection {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+/* how the position along an edge carries over to the next display */
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum EdgeMapping {
+    #[default]
+    Absolute, // keep the offset; only the shared span of the edges connects
+    Proportional, // same fraction of the whole edge; of the shared span where an edge is split
+}
+
+/* what happens where two edges meet */
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CornerMode {
+    #[default]
+    Dominant, // the edge the cursor moves towards the most
+    Block, // no switching within `corner_size` of a corner where two edges lead elsewhere
+}
+
+/* options deciding whether and where the cursor leaves a display */
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Crossing {
+    pub mapping: EdgeMapping,
+    pub corner: CornerMode,
+    pub corner_size: i32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
 pub struct WarpZone {
     pub start: i32,
     pub end: i32,
     pub direction: ZoneDirection,
     pub to: Did,
+    pub target: Option<(i32, i32)>, // span of the `to` edge the zone leads to unless adjacent
+    pub kind: ZoneKind,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ZoneKind {
+    Adjacent, // the displays share the edge
+    Link,     // declared in server_config.json
+    Wrap,     // outer edges of the layout joined together
 }
 
 #[derive(Debug)]
 pub struct AssignedDisplays {
     pub system: Vec<Did>,
     pub client: Vec<Did>,
+    pub handles: DisplayHandles,
+}
+
+/* machines number their displays on their own; the server hands out unique ids per (owner, local id) */
+#[derive(Debug, Default)]
+pub struct DisplayHandles {
+    map: HashMap<(Cid, Did), Did>,
+    next: Did,
+}
+
+impl DisplayHandles {
+    /// same display of the same owner always gets the same handle
+    pub fn assign(&mut self, owner: Cid, local: Did) -> Did {
+        let next = &mut self.next;
+
+        *self.map.entry((owner, local)).or_insert_with(|| {
+            *next += 1;
+            *next
+        })
+    }
+
+    /// handle of a display that has one already
+    pub fn get(&self, owner: Cid, local: Did) -> Option<Did> {
+        self.map.get(&(owner, local)).copied()
+    }
+
+    /// owner of the display behind a handle and the id the owner knows it by
+    pub fn local(&self, id: Did) -> Option<(Cid, Did)> {
+        self.map
+            .iter()
+            .find(|(_, handle)| **handle == id)
+            .map(|(key, _)| *key)
+    }
+
+    /// replace the local ids of the displays with their handles
+    pub fn assign_all(&mut self, displays: &mut [Display]) -> Result<(), Error> {
+        for (i, disp) in displays.iter().enumerate() {
+            if displays[..i].iter().any(|x| x.local == disp.local) {
+                return Err(Error::new(
+                    InvalidInput,
+                    format!("duplicate display id {}", disp.local),
+                ));
+            }
+        }
+
+        for disp in displays.iter_mut() {
+            disp.id = self.assign(disp.owner, disp.local);
+        }
+
+        Ok(())
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Display {
     pub name: String,
     pub id: Did,
-    pub x: i32,
+    #[serde(default)]
+    pub local: Did, // id on the owner machine; `id` is the server-wide handle
+    pub x: i32, // x, y: position in the global layout
     pub y: i32,
-    pub width: i32,
+    #[serde(default)]
+    pub system_x: i32, // system_x, system_y: position in the owner's OS coordinates
+    #[serde(default)]
+    pub system_y: i32,
+    pub width: i32, // width, height: unrotated panel resolution; see size()
     pub height: i32,
-    pub rotation: f32,
+    #[serde(default)]
+    pub width_mm: i32,
+    #[serde(default)]
+    pub height_mm: i32,
+    pub rotation: f32, // clockwise degrees
     pub scale_factor: f32,
+    #[serde(default = "default_scale")]
+    pub pixel_ratio: f32, // OS cursor pixels per layout pixel
     pub frequency: f32,
     pub is_primary: bool,
     #[serde(skip)]
@@ -60,17 +164,50 @@ pub struct Display {
 }
 
 impl Display {
-    pub fn from(item: DisplayInfo, cid: Cid, display_scale: f32) -> Self {
+    pub fn from(item: DisplayInfo, cid: Cid) -> Self {
+        // X11 reports the geometry divided by the scale factor but moves the cursor in pixels
+        let pixel_ratio = if cfg!(target_os = "linux") {
+            item.scale_factor
+        } else {
+            1.0
+        };
+
+        // the OS reports the rotated geometry
+        let (width, height, width_mm, height_mm) = match quarter_turns(item.rotation) {
+            1 | 3 => (item.height, item.width, item.height_mm, item.width_mm),
+            _ => (item.width, item.height, item.width_mm, item.height_mm),
+        };
+
+        let (width, height) = (width as i32, height as i32);
+
+        // drivers may not know the physical size; only windows reports the geometry unscaled
+        let logical = if cfg!(target_os = "windows") {
+            item.scale_factor
+        } else {
+            1.0
+        };
+
+        let (width_mm, height_mm) = match (width_mm, height_mm) {
+            (w, h) if w > 0 && h > 0 => (w, h),
+            _ => (estimate_mm(width, logical), estimate_mm(height, logical)),
+        };
+
         Display {
             name: item.name,
             id: item.id,
+            local: item.id,
             // raw_handle - cannot serialize
             x: item.x,
             y: item.y,
-            width: item.width as i32,
-            height: item.height as i32,
+            system_x: (item.x as f32 * pixel_ratio).round() as i32,
+            system_y: (item.y as f32 * pixel_ratio).round() as i32,
+            width,
+            height,
+            width_mm,
+            height_mm,
             rotation: item.rotation,
-            scale_factor: display_scale,
+            scale_factor: item.scale_factor,
+            pixel_ratio,
             frequency: item.frequency,
             is_primary: item.is_primary,
             warpzones: Vec::new(),
@@ -78,11 +215,35 @@ impl Display {
         }
     }
 
+    /* layout pixels per millimeter; how far the cursor moves for the same hand motion */
+    pub fn pixels_per_mm(&self) -> f64 {
+        if self.width_mm > 0 {
+            self.width as f64 / self.width_mm as f64
+        } else {
+            96.0 / 25.4
+        }
+    }
+
+    pub fn quarter_turns(&self) -> i32 {
+        quarter_turns(self.rotation)
+    }
+
+    /* width and height as laid out on the screen */
+    pub fn size(&self) -> (i32, i32) {
+        match self.quarter_turns() {
+            1 | 3 => (self.height, self.width),
+            _ => (self.width, self.height),
+        }
+    }
+
     pub fn is_overlap(&self, target: Display) -> bool {
-        let self_right = self.x + self.width;
-        let self_bottom = self.y + self.height;
-        let target_right = target.x + target.width;
-        let target_bottom = target.y + target.height;
+        let (width, height) = self.size();
+        let (target_width, target_height) = target.size();
+
+        let self_right = self.x + width;
+        let self_bottom = self.y + height;
+        let target_right = target.x + target_width;
+        let target_bottom = target.y + target_height;
 
         self.x < target_right
             && self_right > target.x
@@ -91,10 +252,13 @@ impl Display {
     }
 
     pub fn is_touch(&self, target: Display) -> Option<(i32, i32, ZoneDirection)> {
-        let self_right = self.x + self.width;
-        let self_bottom = self.y + self.height;
-        let target_right = target.x + target.width;
-        let target_bottom = target.y + target.height;
+        let (width, height) = self.size();
+        let (target_width, target_height) = target.size();
+
+        let self_right = self.x + width;
+        let self_bottom = self.y + height;
+        let target_right = target.x + target_width;
+        let target_bottom = target.y + target_height;
 
         let horizontal_touch = (self_right == target.x || self.x == target_right)
             && (self.y < target_bottom && self_bottom > target.y);
@@ -103,7 +267,7 @@ impl Display {
             && (self.x < target_right && self_right > target.x);
 
         if horizontal_touch {
-            return Some((
+            Some((
                 i32::max(self.y, target.y),
                 i32::min(self_bottom, target_bottom),
                 if self_right == target.x {
@@ -111,9 +275,9 @@ impl Display {
                 } else {
                     ZoneDirection::HorizontalLeft
                 },
-            ));
+            ))
         } else if vertical_touch {
-            return Some((
+            Some((
                 i32::max(self.x, target.x),
                 i32::min(self_right, target_right),
                 if self_bottom == target.y {
@@ -121,44 +285,466 @@ impl Display {
                 } else {
                     ZoneDirection::VerticalUp
                 },
-            ));
+            ))
         } else {
             None
         }
     }
+
+    /*
+     * coordinate spaces
+     *   global: the layout all machines share; `x`, `y` place the display in it
+     *   local:  panel pixels from the top left corner of the unrotated display
+     *   system: what the owner's OS uses; `system_x`, `system_y` place the display in it
+     */
+    pub fn global_to_local(&self, x: i32, y: i32) -> (i32, i32) {
+        self.unrotate(x - self.x, y - self.y)
+    }
+
+    pub fn local_to_global(&self, x: i32, y: i32) -> (i32, i32) {
+        let (x, y) = self.rotate(x, y);
+        (self.x + x, self.y + y)
+    }
+
+    pub fn system_to_local(&self, x: i32, y: i32) -> (i32, i32) {
+        let ratio = self.pixel_ratio as f64;
+
+        self.unrotate(
+            ((x - self.system_x) as f64 / ratio).round() as i32,
+            ((y - self.system_y) as f64 / ratio).round() as i32,
+        )
+    }
+
+    pub fn local_to_system(&self, x: i32, y: i32) -> (i32, i32) {
+        let ratio = self.pixel_ratio as f64;
+        let (x, y) = self.rotate(x, y);
+
+        (
+            self.system_x + (x as f64 * ratio).round() as i32,
+            self.system_y + (y as f64 * ratio).round() as i32,
+        )
+    }
+
+    /* panel pixel to its offset on the screen */
+    fn rotate(&self, x: i32, y: i32) -> (i32, i32) {
+        let (w, h) = (self.width, self.height);
+
+        match self.quarter_turns() {
+            1 => (h - 1 - y, x),
+            2 => (w - 1 - x, h - 1 - y),
+            3 => (y, w - 1 - x),
+            _ => (x, y),
+        }
+    }
+
+    fn unrotate(&self, x: i32, y: i32) -> (i32, i32) {
+        let (w, h) = (self.width, self.height);
+
+        match self.quarter_turns() {
+            1 => (y, h - 1 - x),
+            2 => (w - 1 - x, h - 1 - y),
+            3 => (w - 1 - y, x),
+            _ => (x, y),
+        }
+    }
+
+    pub fn global_to_system(&self, x: i32, y: i32) -> (i32, i32) {
+        let (x, y) = self.global_to_local(x, y);
+        self.local_to_system(x, y)
+    }
+
+    pub fn system_to_global(&self, x: i32, y: i32) -> (i32, i32) {
+        let (x, y) = self.system_to_local(x, y);
+        self.local_to_global(x, y)
+    }
+
+    /* (x, y) in global coordinates */
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        let (width, height) = self.size();
+
+        x >= self.x && x < self.x + width && y >= self.y && y < self.y + height
+    }
+
+    /* warpzone the cursor at (x, y) moving by `motion` is about to pass through */
+    pub fn find_warpzone(
+        &self,
+        x: i32,
+        y: i32,
+        motion: (i32, i32),
+        crossing: &Crossing,
+    ) -> Option<&WarpZone> {
+        let (width, height) = self.size();
+        let (dx, dy) = motion;
+
+        if crossing.corner == CornerMode::Block {
+            let size = crossing.corner_size.max(MARGIN);
+
+            // the edges of the corner the cursor is in and the stretch of each within the corner
+            let side_x = if x <= self.x + size {
+                Some((ZoneDirection::HorizontalLeft, self.x, self.x + size))
+            } else if x >= self.x + width - size {
+                Some((
+                    ZoneDirection::HorizontalRight,
+                    self.x + width - size,
+                    self.x + width,
+                ))
+            } else {
+                None
+            };
+
+            let side_y = if y <= self.y + size {
+                Some((ZoneDirection::VerticalUp, self.y, self.y + size))
+            } else if y >= self.y + height - size {
+                Some((
+                    ZoneDirection::VerticalDown,
+                    self.y + height - size,
+                    self.y + height,
+                ))
+            } else {
+                None
+            };
+
+            let leads = |direction: ZoneDirection, start: i32, end: i32| {
+                self.is_proportional(direction, crossing.mapping)
+                    || self
+                        .warpzones
+                        .iter()
+                        .any(|wz| wz.direction == direction && wz.start < end && wz.end > start)
+            };
+
+            // only where two ways out meet is it unclear which one was meant
+            if let (Some((dir_x, x_start, x_end)), Some((dir_y, y_start, y_end))) = (side_x, side_y)
+            {
+                if leads(dir_x, y_start, y_end) && leads(dir_y, x_start, x_end) {
+                    return None;
+                }
+            }
+        }
+
+        // no motion known; every edge the cursor stands on counts
+        let unknown = motion == (0, 0);
+
+        self.warpzones
+            .iter()
+            .enumerate()
+            .filter(|(_, wz)| {
+                let (along, at_edge, pushing) = match wz.direction {
+                    ZoneDirection::HorizontalLeft => (y, x <= self.x + MARGIN, dx < 0),
+                    ZoneDirection::HorizontalRight => (y, x >= (self.x + width) - MARGIN, dx > 0),
+                    ZoneDirection::VerticalUp => (x, y <= self.y + MARGIN, dy < 0),
+                    ZoneDirection::VerticalDown => (x, y >= (self.y + height) - MARGIN, dy > 0),
+                };
+
+                // a proportional edge is a warpzone from end to end
+                at_edge
+                    && (pushing || unknown)
+                    && ((along >= wz.start - MARGIN && along <= wz.end + MARGIN)
+                        || self.is_proportional(wz.direction, crossing.mapping))
+            })
+            // in corners the edge the cursor moves towards the most wins, then horizontal edges,
+            // then whatever comes first; declared links are in front
+            .min_by_key(|(i, wz)| match wz.direction {
+                ZoneDirection::HorizontalLeft | ZoneDirection::HorizontalRight => {
+                    (-dx.abs(), 0, *i)
+                }
+                ZoneDirection::VerticalUp | ZoneDirection::VerticalDown => (-dy.abs(), 1, *i),
+            })
+            .map(|(_, wz)| wz)
+    }
+
+    /* an edge maps proportionally from end to end only with a single display on the other side */
+    fn is_proportional(&self, direction: ZoneDirection, mapping: EdgeMapping) -> bool {
+        mapping == EdgeMapping::Proportional
+            && self
+                .warpzones
+                .iter()
+                .filter(|wz| wz.direction == direction)
+                .count()
+                == 1
+    }
+
+    /* where the cursor at (x, y) lands on `to` after passing through `wz` */
+    pub fn cross(
+        &self,
+        wz: &WarpZone,
+        to: &Display,
+        x: i32,
+        y: i32,
+        mapping: EdgeMapping,
+    ) -> (i32, i32) {
+        let scale = |along: i32, from: i32, from_len: i32, to: i32, to_len: i32| {
+            let ratio = (along - from) as f64 / (from_len - 1).max(1) as f64;
+            to + (ratio * (to_len - 1) as f64).round() as i32
+        };
+
+        // declared links map their own span onto the declared span
+        if let Some((start, end)) = wz.target {
+            let (x, y) = match wz.direction {
+                ZoneDirection::HorizontalLeft | ZoneDirection::HorizontalRight => {
+                    (x, scale(y, wz.start, wz.end - wz.start, start, end - start))
+                }
+                ZoneDirection::VerticalUp | ZoneDirection::VerticalDown => {
+                    (scale(x, wz.start, wz.end - wz.start, start, end - start), y)
+                }
+            };
+
+            return to.entry_point(wz.direction, x, y);
+        }
+
+        if mapping != EdgeMapping::Proportional {
+            return to.entry_point(wz.direction, x, y);
+        }
+
+        // same fraction of the mapped span on both sides; crossing back lands where it came from
+        let (start, end) = self.proportional_span(wz.direction, wz, mapping);
+        let (to_start, to_end) = to.proportional_span(wz.direction.reverse(), wz, mapping);
+        let (len, to_len) = (end - start, to_end - to_start);
+
+        let (x, y) = match wz.direction {
+            ZoneDirection::HorizontalLeft | ZoneDirection::HorizontalRight => {
+                (x, scale(y, start, len, to_start, to_len))
+            }
+            ZoneDirection::VerticalUp | ZoneDirection::VerticalDown => {
+                (scale(x, start, len, to_start, to_len), y)
+            }
+        };
+
+        to.entry_point(wz.direction, x, y)
+    }
+
+    /* the whole edge if it leads to a single display; the span shared through `wz` if it is split */
+    fn proportional_span(
+        &self,
+        direction: ZoneDirection,
+        wz: &WarpZone,
+        mapping: EdgeMapping,
+    ) -> (i32, i32) {
+        if self.is_proportional(direction, mapping) {
+            self.edge_span(direction, None)
+        } else {
+            (wz.start, wz.end)
+        }
+    }
+
+    /* global span of the edge facing `direction`; `range` is relative to the top or left end */
+    pub fn edge_span(&self, direction: ZoneDirection, range: Option<(i32, i32)>) -> (i32, i32) {
+        let (width, height) = self.size();
+
+        let (origin, len) = match direction {
+            ZoneDirection::HorizontalLeft | ZoneDirection::HorizontalRight => (self.y, height),
+            ZoneDirection::VerticalUp | ZoneDirection::VerticalDown => (self.x, width),
+        };
+
+        let (start, end) = range.unwrap_or((0, len));
+
+        (origin + start.clamp(0, len), origin + end.clamp(0, len))
+    }
+
+    /* where a cursor moving in `direction` at (x, y) lands; kept clear of the edge so it does not bounce back */
+    pub fn entry_point(&self, direction: ZoneDirection, x: i32, y: i32) -> (i32, i32) {
+        let (width, height) = self.size();
+
+        // displays a few pixels across get the middle
+        let inset_x = (MARGIN + 1).min((width - 1) / 2);
+        let inset_y = (MARGIN + 1).min((height - 1) / 2);
+
+        let x = x.clamp(self.x + inset_x, self.x + width - 1 - inset_x);
+        let y = y.clamp(self.y + inset_y, self.y + height - 1 - inset_y);
+
+        match direction {
+            ZoneDirection::HorizontalRight => (self.x + inset_x, y),
+            ZoneDirection::HorizontalLeft => (self.x + width - 1 - inset_x, y),
+            ZoneDirection::VerticalDown => (x, self.y + inset_y),
+            ZoneDirection::VerticalUp => (x, self.y + height - 1 - inset_y),
+        }
+    }
+}
+
+/* where a machine learns about its displays */
+pub trait DisplaySource {
+    fn displays(&self, owner: Cid) -> Result<Vec<Display>, Error>;
+}
+
+/* displays reported by the OS */
+pub struct SystemDisplays;
+
+impl DisplaySource for SystemDisplays {
+    fn displays(&self, owner: Cid) -> Result<Vec<Display>, Error> {
+        let displays = DisplayInfo::all()
+            .map_err(|e| Error::new(NotFound, format!("failed to get system displays: {}", e)))?;
+
+        Ok(displays
+            .into_iter()
+            .map(|x| Display::from(x, owner))
+            .collect())
+    }
+}
+
+/* declared display; what a headless machine pretends to have */
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DisplaySpec {
+    pub name: String,
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+    #[serde(default)]
+    pub width_mm: i32,
+    #[serde(default)]
+    pub height_mm: i32,
+    #[serde(default)]
+    pub rotation: f32,
+    #[serde(default = "default_scale")]
+    pub scale_factor: f32,
+    #[serde(default)]
+    pub is_primary: bool,
+}
+
+fn default_scale() -> f32 {
+    1.0
+}
+
+/* clockwise degrees to quarter turns in 0..4 */
+fn quarter_turns(rotation: f32) -> i32 {
+    ((rotation / 90.0).round() as i32).rem_euclid(4)
+}
+
+/* physical size of `pixels` at 96 dpi times the scale */
+fn estimate_mm(pixels: i32, scale: f32) -> i32 {
+    (pixels as f32 / (96.0 * scale) * 25.4).round() as i32
+}
+
+impl DisplaySpec {
+    pub fn to_display(&self, id: Did, owner: Cid) -> Display {
+        Display {
+            name: self.name.clone(),
+            id,
+            local: id,
+            x: self.x,
+            y: self.y,
+            system_x: self.x,
+            system_y: self.y,
+            width: self.width,
+            height: self.height,
+            width_mm: match self.width_mm {
+                0 => estimate_mm(self.width, self.scale_factor),
+                mm => mm,
+            },
+            height_mm: match self.height_mm {
+                0 => estimate_mm(self.height, self.scale_factor),
+                mm => mm,
+            },
+            rotation: self.rotation,
+            scale_factor: self.scale_factor,
+            pixel_ratio: 1.0,
+            frequency: 0.0,
+            is_primary: self.is_primary,
+            warpzones: Vec::new(),
+            owner,
+        }
+    }
+}
+
+fn from_specs(specs: &[DisplaySpec], owner: Cid) -> Result<Vec<Display>, Error> {
+    if specs.is_empty() {
+        return Err(Error::new(NotFound, "no display declared"));
+    }
+
+    // first declared display is primary unless told otherwise
+    let primary = specs.iter().position(|x| x.is_primary).unwrap_or(0);
+
+    Ok(specs
+        .iter()
+        .enumerate()
+        .map(|(i, spec)| {
+            let mut disp = spec.to_display(i as Did + 1, owner);
+            disp.is_primary = i == primary;
+            disp
+        })
+        .collect())
+}
+
+/* displays declared in a json file; an array of DisplaySpec */
+pub struct JsonDisplays {
+    pub file: PathBuf,
+}
+
+impl DisplaySource for JsonDisplays {
+    fn displays(&self, owner: Cid) -> Result<Vec<Display>, Error> {
+        let json = fs::read_to_string(&self.file)?;
+        let specs: Vec<DisplaySpec> = serde_json::from_str(&json)?;
+
+        from_specs(&specs, owner)
+    }
+}
+
+/* in-memory virtual monitors */
+pub struct VirtualDisplays {
+    pub specs: Vec<DisplaySpec>,
 }
 
-pub fn create_warpzones(a: &mut Vec<Display>, b: &mut Vec<Display>, eq: bool) -> Result<(), Error> {
+impl VirtualDisplays {
+    /// parse `WIDTHxHEIGHT[+X+Y]`, comma separated; displays without a position are placed left to right
+    pub fn parse(spec: &str) -> Result<VirtualDisplays, Error> {
+        let invalid = || Error::new(InvalidInput, format!("invalid display spec: {}", spec));
+        let mut specs = Vec::new();
+        let mut next_x = 0;
+
+        for (i, item) in spec.split(',').enumerate() {
+            let mut parts = item.trim().split('+');
+            let size = parts.next().ok_or_else(invalid)?;
+            let (width, height) = size.split_once('x').ok_or_else(invalid)?;
+
+            let width: i32 = width.parse().map_err(|_| invalid())?;
+            let height: i32 = height.parse().map_err(|_| invalid())?;
+
+            let (x, y) = match (parts.next(), parts.next()) {
+                (Some(x), Some(y)) => (
+                    x.parse().map_err(|_| invalid())?,
+                    y.parse().map_err(|_| invalid())?,
+                ),
+                (None, None) => (next_x, 0),
+                _ => return Err(invalid()),
+            };
+
+            if width <= 0 || height <= 0 {
+                return Err(invalid());
+            }
+
+            next_x = x + width;
+
+            specs.push(DisplaySpec {
+                name: format!("virtual-{}", i),
+                x,
+                y,
+                width,
+                height,
+                width_mm: 0,
+                height_mm: 0,
+                rotation: 0.0,
+                scale_factor: 1.0,
+                is_primary: i == 0,
+            });
+        }
+
+        Ok(VirtualDisplays { specs })
+    }
+}
+
+impl DisplaySource for VirtualDisplays {
+    fn displays(&self, owner: Cid) -> Result<Vec<Display>, Error> {
+        from_specs(&self.specs, owner)
+    }
+}
+
+pub fn create_warpzones(a: &mut [Display], b: &mut [Display], eq: bool) -> Result<(), Error> {
     for (i, disp) in a.iter_mut().enumerate() {
         for (j, target) in b.iter_mut().enumerate() {
             if eq && i >= j {
                 continue;
             }
 
-            // check overlap
-            if disp.is_overlap(target.clone()) {
-                return Err(Error::new(
-                    InvalidInput,
-                    "displays are overlapping",
-                ));
-            }
-
-            // add warpzone to each other if touching
-            if let Some((start, end, direction)) = disp.is_touch(target.clone()) {
-                disp.warpzones.push(WarpZone {
-                    start,
-                    end,
-                    direction,
-                    to: target.id,
-                });
-
-                target.warpzones.push(WarpZone {
-                    start,
-                    end,
-                    direction: direction.reverse(),
-                    to: disp.id,
-                });
-            }
+            link_pair(disp, target)?;
         }
     }
 
@@ -166,61 +752,1049 @@ pub fn create_warpzones(a: &mut Vec<Display>, b: &mut Vec<Display>, eq: bool) ->
 }
 
 pub fn create_warpzones_hashmap(
-    map: &mut Arc<RwLock<HashMap<u32, Display>>>,
-    b: &mut Vec<Display>,
+    hashmap: &mut HashMap<Did, Display>,
+    b: &mut [Display],
 ) -> Result<Vec<Did>, Error> {
-    let mut hashmap = map.write().unwrap();
-    let a: Vec<Display> = hashmap.values().cloned().collect();
+    let mut ids: Vec<Did> = hashmap.keys().copied().collect();
+    ids.sort();
 
-    let mut new = Vec::new();
+    // with the displays already there; reachability is up to validate_layout
+    for id in ids {
+        let disp = hashmap.get_mut(&id).unwrap();
 
-    // check overlap and isolated displays first
-    for disp in a.iter() {
-        for target in b.iter() {
-            if disp.id == target.id {
-                return Err(Error::new(
-                    InvalidInput,
-                    "display ids are not identical",
-                ));
+        for target in b.iter_mut() {
+            link_pair(target, disp)?;
+        }
+    }
+
+    // between the displays of the same machine
+    for i in 0..b.len() {
+        let (head, tail) = b.split_at_mut(i + 1);
+
+        for target in tail.iter_mut() {
+            link_pair(&mut head[i], target)?;
+        }
+    }
+
+    // insert once every warpzone of a display is known
+    for target in b.iter() {
+        hashmap.insert(target.id, target.clone());
+    }
+
+    Ok(b.iter().map(|x| x.id).collect())
+}
+
+/* warpzones both ways where two displays touch; overlapping displays cannot be placed at all */
+fn link_pair(a: &mut Display, b: &mut Display) -> Result<(), Error> {
+    if a.is_overlap(b.clone()) {
+        return Err(Error::new(
+            InvalidInput,
+            LayoutIssue::Overlap(a.name.clone(), b.name.clone()).to_string(),
+        ));
+    }
+
+    if let Some((start, end, direction)) = a.is_touch(b.clone()) {
+        a.warpzones.push(WarpZone {
+            start,
+            end,
+            direction,
+            to: b.id,
+            target: None,
+            kind: ZoneKind::Adjacent,
+        });
+
+        b.warpzones.push(WarpZone {
+            start,
+            end,
+            direction: direction.reverse(),
+            to: a.id,
+            target: None,
+            kind: ZoneKind::Adjacent,
+        });
+    }
+
+    Ok(())
+}
+
+/* display of a machine as written in server_config.json */
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct DisplayRef {
+    #[serde(default)]
+    pub owner: Cid, // SERVER_CID for the server itself
+    pub name: String,
+}
+
+impl DisplayRef {
+    fn find(&self, displays: &HashMap<Did, Display>) -> Option<Did> {
+        displays
+            .values()
+            .find(|x| x.owner == self.owner && x.name == self.name)
+            .map(|x| x.id)
+    }
+}
+
+/* "the `edge` of `from` leads to the opposite edge of `to`", wherever the displays are */
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DisplayLink {
+    pub from: DisplayRef,
+    pub edge: ZoneDirection,
+    pub to: DisplayRef,
+    #[serde(default)]
+    pub range: Option<(i32, i32)>, // part of the `from` edge; whole edge if omitted
+    #[serde(default)]
+    pub to_range: Option<(i32, i32)>, // part of the `to` edge; whole edge if omitted
+}
+
+/* declared links go before the computed warpzones so they win where both apply */
+pub fn apply_links(displays: &mut HashMap<Did, Display>, links: &[DisplayLink]) {
+    for disp in displays.values_mut() {
+        disp.warpzones.retain(|wz| wz.kind != ZoneKind::Link);
+    }
+
+    for link in links {
+        // the displays of clients that are not connected yet
+        let (from, to) = match (link.from.find(displays), link.to.find(displays)) {
+            (Some(from), Some(to)) => (from, to),
+            _ => continue,
+        };
+
+        let from_span = displays[&from].edge_span(link.edge, link.range);
+        let to_span = displays[&to].edge_span(link.edge.reverse(), link.to_range);
+
+        displays.get_mut(&from).unwrap().warpzones.insert(
+            0,
+            WarpZone {
+                start: from_span.0,
+                end: from_span.1,
+                direction: link.edge,
+                to,
+                target: Some(to_span),
+                kind: ZoneKind::Link,
+            },
+        );
+
+        displays.get_mut(&to).unwrap().warpzones.insert(
+            0,
+            WarpZone {
+                start: to_span.0,
+                end: to_span.1,
+                direction: link.edge.reverse(),
+                to: from,
+                target: Some(from_span),
+                kind: ZoneKind::Link,
+            },
+        );
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum WrapMode {
+    #[default]
+    None,
+    Horizontal, // rightmost edges lead to the leftmost ones
+    Vertical,   // bottom edges lead to the top ones
+    Both,
+}
+
+/* join the outer edges of every row and column of the layout, however uneven */
+pub fn apply_wrap(displays: &mut HashMap<Did, Display>, mode: WrapMode) {
+    for disp in displays.values_mut() {
+        disp.warpzones.retain(|wz| wz.kind != ZoneKind::Wrap);
+    }
+
+    if matches!(mode, WrapMode::Horizontal | WrapMode::Both) {
+        wrap_axis(displays, ZoneDirection::HorizontalRight);
+    }
+
+    if matches!(mode, WrapMode::Vertical | WrapMode::Both) {
+        wrap_axis(displays, ZoneDirection::VerticalDown);
+    }
+}
+
+fn wrap_axis(displays: &mut HashMap<Did, Display>, direction: ZoneDirection) {
+    // (start along the edge, length along the edge, start across, length across)
+    let rect = |d: &Display| {
+        let (width, height) = d.size();
+
+        match direction {
+            ZoneDirection::HorizontalLeft | ZoneDirection::HorizontalRight => {
+                (d.y, height, d.x, width)
             }
+            ZoneDirection::VerticalUp | ZoneDirection::VerticalDown => (d.x, width, d.y, height),
+        }
+    };
 
-            if disp.is_overlap(target.clone()) {
-                return Err(Error::new(
-                    InvalidInput,
-                    "displays are overlapping",
-                ));
+    // every display edge splits the layout into bands
+    let mut cuts: Vec<i32> = displays
+        .values()
+        .flat_map(|d| {
+            let (start, len, ..) = rect(d);
+            [start, start + len]
+        })
+        .collect();
+    cuts.sort();
+    cuts.dedup();
+
+    let mut zones: Vec<(Did, Did, i32, i32)> = Vec::new();
+
+    for band in cuts.windows(2) {
+        let (start, end) = (band[0], band[1]);
+
+        let row: Vec<&Display> = displays
+            .values()
+            .filter(|d| {
+                let (a, len, ..) = rect(d);
+                a <= start && a + len >= end
+            })
+            .collect();
+
+        let last = row.iter().max_by_key(|d| {
+            let (_, _, a, len) = rect(d);
+            a + len
+        });
+        let first = row.iter().min_by_key(|d| rect(d).2);
+
+        let (last, first) = match (last, first) {
+            (Some(last), Some(first)) => (last, first),
+            _ => continue, // hole in the layout
+        };
+
+        // outer edges that already lead somewhere stay as they are
+        let covered = |d: &Display, direction: ZoneDirection| {
+            d.warpzones
+                .iter()
+                .any(|wz| wz.direction == direction && wz.start < end && wz.end > start)
+        };
+
+        if covered(last, direction) || covered(first, direction.reverse()) {
+            continue;
+        }
+
+        match zones.last_mut() {
+            Some(zone) if zone.0 == last.id && zone.1 == first.id && zone.3 == start => {
+                zone.3 = end
+            }
+            _ => zones.push((last.id, first.id, start, end)),
+        }
+    }
+
+    for (from, to, start, end) in zones {
+        displays.get_mut(&from).unwrap().warpzones.push(WarpZone {
+            start,
+            end,
+            direction,
+            to,
+            target: Some((start, end)),
+            kind: ZoneKind::Wrap,
+        });
+
+        displays.get_mut(&to).unwrap().warpzones.push(WarpZone {
+            start,
+            end,
+            direction: direction.reverse(),
+            to: from,
+            target: Some((start, end)),
+            kind: ZoneKind::Wrap,
+        });
+    }
+}
+
+/* displays closer than this without touching are most likely a typo in the layout;
+ * well above the few pixels hand-placed or scaled coordinates are off by,
+ * well below a gap left on purpose. `gap_tolerance` in server_config.json */
+pub const GAP_TOLERANCE: i32 = 64;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LayoutIssue {
+    Overlap(String, String),
+    Unreachable(String),
+    Gap(String, String, i32),
+    DuplicateEdge(String, String),
+}
+
+impl LayoutIssue {
+    /// the layout cannot be used as it is
+    pub fn is_fatal(&self) -> bool {
+        matches!(
+            self,
+            LayoutIssue::Overlap(..) | LayoutIssue::Unreachable(..)
+        )
+    }
+}
+
+impl fmt::Display for LayoutIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LayoutIssue::Overlap(a, b) => write!(f, "display {} overlaps display {}", a, b),
+            LayoutIssue::Unreachable(a) => write!(
+                f,
+                "display {} cannot be reached from the server displays",
+                a
+            ),
+            LayoutIssue::Gap(a, b, gap) => write!(
+                f,
+                "display {} is {}px away from display {}; they do not connect",
+                a, gap, b
+            ),
+            LayoutIssue::DuplicateEdge(a, b) => {
+                write!(f, "display {} is connected to display {} twice", a, b)
+            }
+        }
+    }
+}
+
+/* treat the layout as a graph of displays connected by warpzones; gaps up to `gap_tolerance` are reported */
+pub fn validate_layout(displays: &HashMap<Did, Display>, gap_tolerance: i32) -> Vec<LayoutIssue> {
+    layout_issues(displays, gap_tolerance)
+        .into_iter()
+        .map(|(issue, _)| issue)
+        .collect()
+}
+
+/* validate_layout with the owners of the displays each issue is about */
+pub fn layout_issues(
+    displays: &HashMap<Did, Display>,
+    gap_tolerance: i32,
+) -> Vec<(LayoutIssue, Vec<Cid>)> {
+    let mut issues = Vec::new();
+    let mut all: Vec<&Display> = displays.values().collect();
+    all.sort_by_key(|x| x.id);
+
+    for (i, a) in all.iter().enumerate() {
+        let (width, height) = a.size();
+
+        for b in all.iter().skip(i + 1) {
+            let (b_width, b_height) = b.size();
+
+            if a.is_overlap((*b).clone()) {
+                let issue = LayoutIssue::Overlap(a.name.clone(), b.name.clone());
+                issues.push((issue, vec![a.owner, b.owner]));
+                continue;
+            }
+
+            // distance between facing edges, if they face each other at all
+            let rows = a.y < b.y + b_height && b.y < a.y + height;
+            let columns = a.x < b.x + b_width && b.x < a.x + width;
+
+            let gap = if rows {
+                i32::max(b.x - (a.x + width), a.x - (b.x + b_width))
+            } else if columns {
+                i32::max(b.y - (a.y + height), a.y - (b.y + b_height))
+            } else {
+                continue;
+            };
+
+            // bridged by a declared link
+            let linked = a.warpzones.iter().any(|wz| wz.to == b.id);
+
+            if gap > 0 && gap <= gap_tolerance && !linked {
+                let issue = LayoutIssue::Gap(a.name.clone(), b.name.clone(), gap);
+                issues.push((issue, vec![a.owner, b.owner]));
+            }
+        }
+
+        // same neighbor on the same edge more than once
+        for (j, wz) in a.warpzones.iter().enumerate() {
+            let duplicate = a.warpzones[..j]
+                .iter()
+                .any(|x| x.to == wz.to && x.direction == wz.direction && x.target == wz.target);
+
+            if duplicate {
+                let (to, owner) = displays
+                    .get(&wz.to)
+                    .map_or(("?".to_string(), a.owner), |x| (x.name.clone(), x.owner));
+                let issue = LayoutIssue::DuplicateEdge(a.name.clone(), to);
+                issues.push((issue, vec![a.owner, owner]));
+            }
+        }
+    }
+
+    /* every display must be reachable from the server displays */
+    let mut reached: Vec<Did> = all
+        .iter()
+        .filter(|x| x.owner == SERVER_CID)
+        .map(|x| x.id)
+        .collect();
+    let mut queue = reached.clone();
+
+    while let Some(id) = queue.pop() {
+        for wz in displays.get(&id).map_or(&[][..], |x| &x.warpzones) {
+            if !reached.contains(&wz.to) {
+                reached.push(wz.to);
+                queue.push(wz.to);
+            }
+        }
+    }
+
+    for disp in all.iter().filter(|x| !reached.contains(&x.id)) {
+        let issue = LayoutIssue::Unreachable(disp.name.clone());
+        issues.push((issue, vec![disp.owner]));
+    }
+
+    issues
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn virtual_displays_line_up_left_to_right() {
+        let disps = VirtualDisplays::parse("1920x1080, 1280x1024")
+            .unwrap()
+            .displays(3)
+            .unwrap();
+
+        assert_eq!(disps.len(), 2);
+        assert_eq!(
+            (disps[0].x, disps[0].y, disps[0].width, disps[0].height),
+            (0, 0, 1920, 1080)
+        );
+        assert_eq!(
+            (disps[1].x, disps[1].y, disps[1].width, disps[1].height),
+            (1920, 0, 1280, 1024)
+        );
+        assert_eq!(disps[0].name, "virtual-0");
+        assert!(disps[0].is_primary && !disps[1].is_primary);
+        assert!(disps.iter().all(|x| x.owner == 3));
+    }
+
+    #[test]
+    fn virtual_displays_take_positions() {
+        let disps = VirtualDisplays::parse("800x600+-800+100,1024x768")
+            .unwrap()
+            .displays(0)
+            .unwrap();
+
+        assert_eq!((disps[0].x, disps[0].y), (-800, 100));
+        // unpositioned displays continue right of the previous one
+        assert_eq!((disps[1].x, disps[1].y), (0, 0));
+    }
+
+    #[test]
+    fn invalid_virtual_displays() {
+        for spec in [
+            "",
+            "1920",
+            "1920x",
+            "axb",
+            "0x1080",
+            "1920x-1",
+            "1920x1080+10",
+            "1920x1080+a+b",
+        ] {
+            let e = VirtualDisplays::parse(spec)
+                .err()
+                .unwrap_or_else(|| panic!("{:?} parsed", spec));
+            assert_eq!(e.kind(), InvalidInput, "{:?}", spec);
+        }
+    }
+
+    #[test]
+    fn json_displays() {
+        let file =
+            std::env::temp_dir().join(format!("transistor-displays-{}.json", std::process::id()));
+        let json = r#"[
+            { "name": "left", "x": -1280, "y": 0, "width": 1280, "height": 1024 },
+            { "name": "main", "x": 0, "y": 0, "width": 2560, "height": 1440,
+              "scale_factor": 2.0, "is_primary": true }
+        ]"#;
+        fs::write(&file, json).unwrap();
+
+        let disps = JsonDisplays { file: file.clone() }.displays(5).unwrap();
+        fs::remove_file(&file).unwrap();
+
+        assert_eq!(disps.len(), 2);
+        assert_eq!(
+            (disps[0].name.as_str(), disps[0].x, disps[0].width),
+            ("left", -1280, 1280)
+        );
+        assert_eq!(disps[1].scale_factor, 2.0);
+        // the declared primary wins over the first one
+        assert!(!disps[0].is_primary && disps[1].is_primary);
+        assert!(disps.iter().all(|x| x.owner == 5));
+    }
+
+    #[test]
+    fn json_displays_errors() {
+        let file = std::env::temp_dir().join(format!(
+            "transistor-no-displays-{}.json",
+            std::process::id()
+        ));
+
+        let missing = JsonDisplays { file: file.clone() }.displays(0).unwrap_err();
+        assert_eq!(missing.kind(), NotFound);
+
+        fs::write(&file, "[]").unwrap();
+        let empty = JsonDisplays { file: file.clone() }.displays(0).unwrap_err();
+        assert_eq!(empty.kind(), NotFound);
+
+        fs::write(&file, "{").unwrap();
+        let invalid = JsonDisplays { file: file.clone() }.displays(0).unwrap_err();
+        assert_eq!(invalid.kind(), InvalidData);
+
+        fs::remove_file(&file).unwrap();
+    }
+
+    #[test]
+    fn same_local_id_on_two_machines_gets_two_handles() {
+        let mut handles = DisplayHandles::default();
+
+        let server = handles.assign(SERVER_CID, 1);
+        let client = handles.assign(7, 1);
+
+        assert_ne!(server, client);
+        // and keeps them
+        assert_eq!(handles.assign(7, 1), client);
+        assert_eq!(handles.assign(SERVER_CID, 1), server);
+    }
+
+    #[test]
+    fn handles_lead_back_to_the_local_ids() {
+        let mut handles = DisplayHandles::default();
+        let mut disps = VirtualDisplays::parse("1920x1080,1920x1080")
+            .unwrap()
+            .displays(7)
+            .unwrap();
+
+        handles.assign(SERVER_CID, 1);
+
+        for disp in disps.iter_mut() {
+            disp.local = disp.id;
+        }
+        handles.assign_all(&mut disps).unwrap();
+
+        for disp in disps.iter() {
+            assert_eq!(handles.get(7, disp.local), Some(disp.id));
+            assert_eq!(handles.local(disp.id), Some((7, disp.local)));
+        }
+
+        assert_eq!(handles.get(8, 1), None);
+        assert_eq!(handles.local(99), None);
+    }
+
+    #[test]
+    fn duplicate_local_ids_get_no_handles() {
+        let mut handles = DisplayHandles::default();
+        let mut disps = VirtualDisplays::parse("1920x1080,1920x1080")
+            .unwrap()
+            .displays(7)
+            .unwrap();
+
+        for disp in disps.iter_mut() {
+            disp.local = 1;
+        }
+
+        let e = handles.assign_all(&mut disps).unwrap_err();
+        assert_eq!(e.kind(), InvalidInput);
+        assert_eq!(handles.get(7, 1), None);
+    }
+
+    // server displays from `server`, client 1 displays from `client`; warpzones where they touch
+    fn layout(server: &str, client: &str) -> HashMap<Did, Display> {
+        let mut disps = VirtualDisplays::parse(server)
+            .unwrap()
+            .displays(SERVER_CID)
+            .unwrap();
+        let others = VirtualDisplays::parse(client).unwrap().displays(1).unwrap();
+
+        for (i, mut disp) in others.into_iter().enumerate() {
+            disp.id = (disps.len() + 1) as Did;
+            disp.name = format!("client-{}", i);
+            disps.push(disp);
+        }
+
+        for j in 1..disps.len() {
+            let (head, tail) = disps.split_at_mut(j);
+
+            for disp in head.iter_mut() {
+                // overlapping displays get no warpzones
+                let _ = create_warpzones(std::slice::from_mut(disp), &mut tail[..1], false);
             }
+        }
 
-            // TODO: verify isolated display
+        disps.into_iter().map(|x| (x.id, x)).collect()
+    }
+
+    // server displays from `server` joined by client 1 with the displays from `client`, as in the handshake
+    fn join(server: &str, client: &str) -> Result<HashMap<Did, Display>, Error> {
+        let mut disps: HashMap<Did, Display> = VirtualDisplays::parse(server)
+            .unwrap()
+            .displays(SERVER_CID)
+            .unwrap()
+            .into_iter()
+            .map(|x| (x.id, x))
+            .collect();
+        let mut others = VirtualDisplays::parse(client).unwrap().displays(1).unwrap();
+
+        for (i, disp) in others.iter_mut().enumerate() {
+            disp.id = (disps.len() + i + 1) as Did;
+            disp.name = format!("client-{}", i);
         }
+
+        create_warpzones_hashmap(&mut disps, &mut others)?;
+
+        Ok(disps)
+    }
+
+    #[test]
+    fn displays_of_one_client_lead_into_each_other() {
+        let disps = join("1920x1080", "1920x1080+1920+0,1280x1024+3840+0").unwrap();
+
+        let right = zone(&disps, 2, 3);
+        assert_eq!(
+            (right.direction, right.start, right.end),
+            (ZoneDirection::HorizontalRight, 0, 1024)
+        );
+        assert_eq!(zone(&disps, 3, 2).direction, ZoneDirection::HorizontalLeft);
+
+        // the far one only through the near one
+        assert!(disps[&1].warpzones.iter().all(|x| x.to == 2));
+        assert!(disps[&3].warpzones.iter().all(|x| x.to == 2));
+        assert_eq!(validate_layout(&disps, GAP_TOLERANCE), vec![]);
     }
 
-    // add warpzones
-    for disp in a.iter() {
-        for target in b.iter() {
-            if let Some((start, end, direction)) = disp.is_touch(target.clone()) {
-                hashmap.get_mut(&disp.id).unwrap().warpzones.push(WarpZone {
-                    start,
-                    end,
-                    direction,
-                    to: target.id,
-                });
+    #[test]
+    fn displays_of_one_client_may_not_overlap() {
+        let e = join("1920x1080", "1920x1080+1920+0,1920x1080+2880+0").unwrap_err();
 
-                let mut target = target.clone();
-                let tid = target.id;
+        assert_eq!(e.kind(), InvalidInput);
+        assert_eq!(
+            e.to_string(),
+            LayoutIssue::Overlap("client-0".into(), "client-1".into()).to_string()
+        );
+    }
 
-                target.warpzones.push(WarpZone {
-                    start,
-                    end,
-                    direction: direction.reverse(),
-                    to: disp.id,
-                });
+    #[test]
+    fn tiny_displays_are_entered_inside() {
+        for spec in ["1x1", "4x3", "6x7", "7x2"] {
+            let disp = &VirtualDisplays::parse(spec).unwrap().displays(1).unwrap()[0];
+            let (width, height) = disp.size();
 
-                hashmap.insert(target.id, target);
-                new.push(tid);
+            for direction in [
+                ZoneDirection::HorizontalLeft,
+                ZoneDirection::HorizontalRight,
+                ZoneDirection::VerticalUp,
+                ZoneDirection::VerticalDown,
+            ] {
+                for (x, y) in [(-100, -100), (0, 0), (100, 100)] {
+                    let (x, y) = disp.entry_point(direction, x, y);
+                    assert!(
+                        (0..width).contains(&x) && (0..height).contains(&y),
+                        "{} {:?}: ({}, {})",
+                        spec,
+                        direction,
+                        x,
+                        y
+                    );
+                }
             }
         }
     }
 
-    Ok(new)
+    #[test]
+    fn touching_layout_is_fine() {
+        let disps = layout("1920x1080", "1920x1080+1920+0");
+        assert_eq!(validate_layout(&disps, GAP_TOLERANCE), vec![]);
+    }
+
+    #[test]
+    fn overlap_is_fatal() {
+        let disps = layout("1920x1080", "1920x1080+1000+0");
+        let issues = validate_layout(&disps, GAP_TOLERANCE);
+
+        assert!(issues.contains(&LayoutIssue::Overlap("virtual-0".into(), "client-0".into())));
+        assert!(issues.iter().all(|x| x.is_fatal()));
+    }
+
+    #[test]
+    fn unreachable_is_fatal() {
+        let disps = layout("1920x1080", "800x600+5000+0");
+        let issues = validate_layout(&disps, GAP_TOLERANCE);
+
+        assert_eq!(issues, vec![LayoutIssue::Unreachable("client-0".into())]);
+        assert!(issues[0].is_fatal());
+    }
+
+    #[test]
+    fn small_gap_is_reported() {
+        let disps = layout("1920x1080", "1920x1080+1950+0");
+        let issues = validate_layout(&disps, GAP_TOLERANCE);
+
+        let gap = LayoutIssue::Gap("virtual-0".into(), "client-0".into(), 30);
+        assert!(issues.contains(&gap));
+        assert!(!gap.is_fatal());
+
+        // wider than the tolerance; left apart on purpose
+        let issues = validate_layout(&disps, 16);
+        assert_eq!(issues, vec![LayoutIssue::Unreachable("client-0".into())]);
+    }
+
+    #[test]
+    fn duplicate_edge_is_reported() {
+        let mut disps = layout("1920x1080", "1920x1080+1920+0");
+        let server = disps.get_mut(&1).unwrap();
+        let again = server.warpzones[0];
+        server.warpzones.push(again);
+
+        let issues = validate_layout(&disps, GAP_TOLERANCE);
+        let duplicate = LayoutIssue::DuplicateEdge("virtual-0".into(), "client-0".into());
+
+        assert_eq!(issues, vec![duplicate.clone()]);
+        assert!(!duplicate.is_fatal());
+    }
+
+    fn zone(disps: &HashMap<Did, Display>, from: Did, to: Did) -> WarpZone {
+        *disps[&from].warpzones.iter().find(|x| x.to == to).unwrap()
+    }
+
+    fn cross(
+        disps: &HashMap<Did, Display>,
+        from: Did,
+        to: Did,
+        x: i32,
+        y: i32,
+        mapping: EdgeMapping,
+    ) -> (i32, i32) {
+        disps[&from].cross(&zone(disps, from, to), &disps[&to], x, y, mapping)
+    }
+
+    #[test]
+    fn proportional_cross_spans_whole_edges() {
+        // 1440p monitor sticking out above and below a 1080p laptop
+        let disps = layout("1920x1080", "2560x1440+1920+-180");
+        let inset = MARGIN + 1;
+
+        assert_eq!(
+            cross(&disps, 1, 2, 1919, 0, EdgeMapping::Proportional),
+            (1920 + inset, -180 + inset)
+        );
+        assert_eq!(
+            cross(&disps, 1, 2, 1919, 1079, EdgeMapping::Proportional),
+            (1920 + inset, 1259 - inset)
+        );
+        assert_eq!(
+            cross(&disps, 1, 2, 1919, 539, EdgeMapping::Proportional),
+            (1920 + inset, 539)
+        );
+
+        // back from the middle of the monitor to the middle of the laptop
+        assert_eq!(
+            cross(&disps, 2, 1, 1920, 539, EdgeMapping::Proportional),
+            (1919 - inset, 539)
+        );
+
+        // absolute keeps the offset
+        assert_eq!(
+            cross(&disps, 1, 2, 1919, 0, EdgeMapping::Absolute),
+            (1920 + inset, 0)
+        );
+    }
+
+    #[test]
+    fn proportional_cross_within_split_edge() {
+        // two monitors stacked right of a laptop, each sharing half of its edge
+        let disps = layout("1920x1080", "1920x1080+1920+-540,1920x1080+1920+540");
+        let inset = MARGIN + 1;
+
+        // the upper half of the laptop edge stretches over the whole upper monitor
+        assert_eq!(
+            cross(&disps, 1, 2, 1919, 0, EdgeMapping::Proportional),
+            (1920 + inset, -540 + inset)
+        );
+        assert_eq!(
+            cross(&disps, 1, 2, 1919, 539, EdgeMapping::Proportional),
+            (1920 + inset, 539 - inset)
+        );
+        assert_eq!(
+            cross(&disps, 1, 3, 1919, 809, EdgeMapping::Proportional),
+            (1920 + inset, 1078)
+        );
+
+        // and back onto the same half
+        assert_eq!(
+            cross(&disps, 2, 1, 1920, 0, EdgeMapping::Proportional),
+            (1919 - inset, 270)
+        );
+        assert_eq!(
+            cross(&disps, 3, 1, 1920, 1619, EdgeMapping::Proportional),
+            (1919 - inset, 1079 - inset)
+        );
+    }
+
+    #[test]
+    fn pixel_ratio_scales_system_coordinates() {
+        let mut disp = VirtualDisplays::parse("1920x1080+1920+0")
+            .unwrap()
+            .displays(0)
+            .unwrap()
+            .remove(0);
+        disp.pixel_ratio = 2.0;
+        disp.system_x = 3840;
+
+        assert_eq!(disp.global_to_system(1920, 0), (3840, 0));
+        assert_eq!(disp.global_to_system(2020, 50), (4040, 100));
+        assert_eq!(disp.system_to_global(4040, 100), (2020, 50));
+    }
+
+    #[test]
+    fn physical_size_follows_the_scale() {
+        let spec = |width: i32, scale_factor: f32| DisplaySpec {
+            name: "panel".into(),
+            x: 0,
+            y: 0,
+            width,
+            height: width * 9 / 16,
+            width_mm: 0,
+            height_mm: 0,
+            rotation: 0.0,
+            scale_factor,
+            is_primary: true,
+        };
+
+        // a 4k panel at 200% is as wide as a 1080p one at 100%, with twice the density
+        let normal = spec(1920, 1.0).to_display(1, 0);
+        let hidpi = spec(3840, 2.0).to_display(1, 0);
+
+        assert_eq!(normal.width_mm, 508);
+        assert_eq!(hidpi.width_mm, 508);
+        assert!((hidpi.pixels_per_mm() / normal.pixels_per_mm() - 2.0).abs() < 1e-9);
+
+        // a declared size wins over the estimate
+        let mut declared = spec(1920, 1.0);
+        declared.width_mm = 254;
+        assert_eq!(declared.to_display(1, 0).width_mm, 254);
+    }
+
+    fn link(from: &str, edge: ZoneDirection, to: &str, range: Option<(i32, i32)>) -> DisplayLink {
+        DisplayLink {
+            from: DisplayRef {
+                owner: SERVER_CID,
+                name: from.into(),
+            },
+            edge,
+            to: DisplayRef {
+                owner: 1,
+                name: to.into(),
+            },
+            range,
+            to_range: None,
+        }
+    }
+
+    #[test]
+    fn links_go_first_on_both_sides() {
+        // apart from each other; only the link connects them
+        let mut disps = layout("1920x1080", "1280x1024+3000+0");
+        let links = [link(
+            "virtual-0",
+            ZoneDirection::HorizontalRight,
+            "client-0",
+            Some((100, 500)),
+        )];
+
+        apply_links(&mut disps, &links);
+
+        let there = disps[&1].warpzones[0];
+        assert_eq!((there.start, there.end, there.to), (100, 500, 2));
+        assert_eq!(
+            (there.direction, there.kind),
+            (ZoneDirection::HorizontalRight, ZoneKind::Link)
+        );
+        assert_eq!(there.target, Some((0, 1024)));
+
+        let back = disps[&2].warpzones[0];
+        assert_eq!((back.start, back.end, back.to), (0, 1024, 1));
+        assert_eq!(
+            (back.direction, back.kind),
+            (ZoneDirection::HorizontalLeft, ZoneKind::Link)
+        );
+        assert_eq!(back.target, Some((100, 500)));
+
+        assert_eq!(validate_layout(&disps, GAP_TOLERANCE), vec![]);
+    }
+
+    #[test]
+    fn links_are_replaced_not_added() {
+        let mut disps = layout("1920x1080", "1920x1080+1920+0");
+        let links = [link(
+            "virtual-0",
+            ZoneDirection::VerticalDown,
+            "client-0",
+            None,
+        )];
+
+        apply_links(&mut disps, &links);
+        apply_links(&mut disps, &links);
+
+        // the adjacent edge stays, behind the link
+        let kinds: Vec<ZoneKind> = disps[&1].warpzones.iter().map(|x| x.kind).collect();
+        assert_eq!(kinds, vec![ZoneKind::Link, ZoneKind::Adjacent]);
+
+        apply_links(&mut disps, &[]);
+        assert!(disps
+            .values()
+            .flat_map(|x| &x.warpzones)
+            .all(|x| x.kind == ZoneKind::Adjacent));
+    }
+
+    #[test]
+    fn links_to_missing_displays_wait() {
+        let mut disps = layout("1920x1080", "1920x1080+1920+0");
+        let before = disps[&1].warpzones.len();
+
+        apply_links(
+            &mut disps,
+            &[link(
+                "virtual-0",
+                ZoneDirection::VerticalUp,
+                "client-9",
+                None,
+            )],
+        );
+
+        assert_eq!(disps[&1].warpzones.len(), before);
+    }
+
+    // wrap zones of a display as (direction, start, end, to), sorted
+    fn wraps(disps: &HashMap<Did, Display>, id: Did) -> Vec<(ZoneDirection, i32, i32, Did)> {
+        let mut wraps: Vec<_> = disps[&id]
+            .warpzones
+            .iter()
+            .filter(|x| x.kind == ZoneKind::Wrap)
+            .map(|x| (x.direction, x.start, x.end, x.to))
+            .collect();
+        wraps.sort_by_key(|x| (x.1, x.0 as u8));
+        wraps
+    }
+
+    #[test]
+    fn wrap_follows_a_staircase() {
+        // the client is half a display lower; every band wraps onto its own outer edges
+        let mut disps = layout("1920x1080", "1920x1080+1920+540");
+        apply_wrap(&mut disps, WrapMode::Horizontal);
+
+        use ZoneDirection::*;
+
+        assert_eq!(
+            wraps(&disps, 1),
+            vec![
+                (HorizontalLeft, 0, 540, 1),
+                (HorizontalRight, 0, 540, 1),
+                (HorizontalLeft, 540, 1080, 2)
+            ]
+        );
+        assert_eq!(
+            wraps(&disps, 2),
+            vec![
+                (HorizontalRight, 540, 1080, 1),
+                (HorizontalLeft, 1080, 1620, 2),
+                (HorizontalRight, 1080, 1620, 2)
+            ]
+        );
+    }
+
+    #[test]
+    fn wrap_skips_holes() {
+        // a display wide hole between the two; nothing wraps through it
+        let mut disps = layout("1920x1080", "1280x720+3840+0");
+        apply_wrap(&mut disps, WrapMode::Vertical);
+
+        use ZoneDirection::*;
+
+        assert_eq!(
+            wraps(&disps, 1),
+            vec![(VerticalUp, 0, 1920, 1), (VerticalDown, 0, 1920, 1)]
+        );
+        assert_eq!(
+            wraps(&disps, 2),
+            vec![(VerticalUp, 3840, 5120, 2), (VerticalDown, 3840, 5120, 2)]
+        );
+
+        // the shorter client ends the rows it is in; the rest of the server edge wraps onto itself
+        apply_wrap(&mut disps, WrapMode::Horizontal);
+
+        assert_eq!(
+            wraps(&disps, 1),
+            vec![
+                (HorizontalLeft, 0, 720, 2),
+                (HorizontalLeft, 720, 1080, 1),
+                (HorizontalRight, 720, 1080, 1)
+            ]
+        );
+        assert_eq!(wraps(&disps, 2), vec![(HorizontalRight, 0, 720, 1)]);
+    }
+
+    fn corner_target(
+        disps: &HashMap<Did, Display>,
+        x: i32,
+        y: i32,
+        motion: (i32, i32),
+        corner: CornerMode,
+    ) -> Option<Did> {
+        let crossing = Crossing {
+            mapping: EdgeMapping::Absolute,
+            corner,
+            corner_size: 16,
+        };
+
+        disps[&1]
+            .find_warpzone(x, y, motion, &crossing)
+            .map(|x| x.to)
+    }
+
+    #[test]
+    fn dominant_corner_follows_the_motion() {
+        // one display right of the server display, one below it
+        let disps = layout("1920x1080", "1920x1080+1920+0,1920x1080+0+1080");
+
+        assert_eq!(
+            corner_target(&disps, 1919, 1079, (5, 1), CornerMode::Dominant),
+            Some(2)
+        );
+        assert_eq!(
+            corner_target(&disps, 1919, 1079, (1, 5), CornerMode::Dominant),
+            Some(3)
+        );
+        // straight into the corner; horizontal edges first
+        assert_eq!(
+            corner_target(&disps, 1919, 1079, (3, 3), CornerMode::Dominant),
+            Some(2)
+        );
+        // moving away from an edge does not cross it
+        assert_eq!(
+            corner_target(&disps, 1919, 1079, (-3, 3), CornerMode::Dominant),
+            Some(3)
+        );
+    }
+
+    #[test]
+    fn blocked_corner_only_where_edges_meet() {
+        let disps = layout("1920x1080", "1920x1080+1920+0,1920x1080+0+1080");
+
+        assert_eq!(
+            corner_target(&disps, 1919, 1079, (5, 1), CornerMode::Block),
+            None
+        );
+        assert_eq!(
+            corner_target(&disps, 1910, 1079, (1, 5), CornerMode::Block),
+            None
+        );
+        assert_eq!(
+            corner_target(&disps, 1919, 1000, (5, 1), CornerMode::Block),
+            Some(2)
+        );
+
+        // the top right corner has a single way out
+        assert_eq!(
+            corner_target(&disps, 1919, 0, (5, -1), CornerMode::Block),
+            Some(2)
+        );
+        // and so does the bottom left one
+        assert_eq!(
+            corner_target(&disps, 0, 1079, (-1, 5), CornerMode::Block),
+            Some(3)
+        );
+    }
 }
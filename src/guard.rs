@@ -0,0 +1,366 @@
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::display::*;
+use crate::input::*;
+
+/* conditions a push against an edge has to meet before the cursor switches; all off by default */
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+pub struct SwitchGuard {
+    #[serde(default)]
+    pub dwell: u64, // ms to keep pushing against the edge
+    #[serde(default)]
+    pub double_tap: u64, // ms between two pushes against the same edge; 0 for a single push
+    #[serde(default)]
+    pub min_speed: f64, // raw device counts per second, before pointer acceleration, to hit the edge with
+    #[serde(default)]
+    pub modifier: Option<ModifierKey>, // key to hold while pushing
+}
+
+/* guard for a single display and/or edge as written in server_config.json */
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GuardRule {
+    #[serde(default)]
+    pub display: Option<DisplayRef>, // every display if omitted
+    #[serde(default)]
+    pub edge: Option<ZoneDirection>, // every edge if omitted
+    #[serde(flatten)]
+    pub guard: SwitchGuard,
+}
+
+impl GuardRule {
+    fn matches(&self, disp: &Display, edge: ZoneDirection) -> bool {
+        self.display
+            .as_ref()
+            .is_none_or(|x| x.owner == disp.owner && x.name == disp.name)
+            && self.edge.is_none_or(|x| x == edge)
+    }
+}
+
+/* motion slower than this between two events starts from rest */
+const SPEED_WINDOW: Duration = Duration::from_millis(100);
+
+/* keeps track of pushes against edges and decides when they may switch displays */
+#[derive(Debug, Clone)]
+pub struct EdgeGuard {
+    default: SwitchGuard,
+    rules: Vec<GuardRule>,
+    lock: Arc<RwLock<bool>>, // no switching at all while set
+    modifiers: Modifiers,
+    last_motion: Option<Instant>,
+    speed: f64,
+    push: Option<Push>,
+    tap: Option<(Did, ZoneDirection, Instant)>, // end of the last push that did not switch
+}
+
+/* a push against a single edge, from arriving at it until leaving it */
+#[derive(Debug, Clone, Copy)]
+struct Push {
+    disp: Did,
+    edge: ZoneDirection,
+    guard: SwitchGuard,
+    since: Instant,
+    speed: f64,
+    second: bool, // second push of a double tap
+    woken: bool,  // a wake for the end of the dwell was asked for
+}
+
+impl EdgeGuard {
+    pub fn new(default: SwitchGuard, rules: Vec<GuardRule>, lock: Arc<RwLock<bool>>) -> Self {
+        EdgeGuard {
+            default,
+            rules,
+            lock,
+            modifiers: Modifiers::default(),
+            last_motion: None,
+            speed: 0.0,
+            push: None,
+            tap: None,
+        }
+    }
+
+    /* first rule that matches the edge wins */
+    pub fn guard(&self, disp: &Display, edge: ZoneDirection) -> SwitchGuard {
+        self.rules
+            .iter()
+            .find(|x| x.matches(disp, edge))
+            .map_or(self.default, |x| x.guard)
+    }
+
+    pub fn key(&mut self, key: Key, pressed: bool) {
+        self.modifiers.update(key, pressed);
+    }
+
+    /* raw pointer motion; keeps the current speed */
+    pub fn motion(&mut self, dx: i32, dy: i32) {
+        let now = Instant::now();
+
+        let elapsed = match self.last_motion {
+            Some(last) => now
+                .duration_since(last)
+                .clamp(Duration::from_millis(1), SPEED_WINDOW),
+            None => SPEED_WINDOW,
+        };
+
+        self.last_motion = Some(now);
+        self.speed = (dx as f64).hypot(dy as f64) / elapsed.as_secs_f64();
+    }
+
+    /* the cursor is pushing against `edge` of `disp`; true if it may switch now */
+    pub fn allow(&mut self, disp: &Display, edge: ZoneDirection) -> bool {
+        if *self.lock.read().unwrap() {
+            return false;
+        }
+
+        let now = Instant::now();
+        let guard = self.guard(disp, edge);
+
+        let push = match self.push {
+            Some(push) if push.disp == disp.id && push.edge == edge => push,
+            _ => {
+                let second = match self.tap.take() {
+                    Some((id, dir, at)) => {
+                        id == disp.id
+                            && dir == edge
+                            && now.duration_since(at) <= Duration::from_millis(guard.double_tap)
+                    }
+                    None => false,
+                };
+
+                let push = Push {
+                    disp: disp.id,
+                    edge,
+                    guard,
+                    since: now,
+                    speed: self.speed,
+                    second,
+                    woken: false,
+                };
+
+                self.push = Some(push);
+                push
+            }
+        };
+
+        let allowed = self.ready(&push)
+            && now.duration_since(push.since) >= Duration::from_millis(guard.dwell);
+
+        if allowed {
+            self.push = None;
+            self.tap = None;
+        }
+
+        allowed
+    }
+
+    /* every condition but the dwell */
+    fn ready(&self, push: &Push) -> bool {
+        let guard = push.guard;
+
+        guard.modifier.is_none_or(|x| self.modifiers.has(x))
+            && push.speed >= guard.min_speed
+            && (guard.double_tap == 0 || push.second)
+    }
+
+    /* time left until the current push has dwelled long enough, if that is all it waits for;
+     * once per push, since a cursor resting against the edge sends nothing to look again */
+    pub fn wake_in(&mut self) -> Option<Duration> {
+        let push = self.push?;
+
+        if push.woken || *self.lock.read().unwrap() || !self.ready(&push) {
+            return None;
+        }
+
+        let left = Duration::from_millis(push.guard.dwell).checked_sub(push.since.elapsed())?;
+        self.push = Some(Push {
+            woken: true,
+            ..push
+        });
+
+        Some(left)
+    }
+
+    /* edge the cursor is pushing against */
+    pub fn pushing(&self) -> Option<ZoneDirection> {
+        self.push.map(|x| x.edge)
+    }
+
+    /* the cursor is on no edge anymore */
+    pub fn leave(&mut self) {
+        if let Some(push) = self.push.take() {
+            self.tap = Some((push.disp, push.edge, Instant::now()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+    use crate::SERVER_CID;
+
+    const EDGE: ZoneDirection = ZoneDirection::HorizontalRight;
+
+    fn display() -> Display {
+        VirtualDisplays::parse("1920x1080")
+            .unwrap()
+            .displays(SERVER_CID)
+            .unwrap()
+            .remove(0)
+    }
+
+    fn edge_guard(guard: SwitchGuard) -> EdgeGuard {
+        EdgeGuard::new(guard, Vec::new(), Arc::new(RwLock::new(false)))
+    }
+
+    #[test]
+    fn unguarded_edges_switch_at_once() {
+        let mut guard = edge_guard(SwitchGuard::default());
+
+        assert!(guard.allow(&display(), EDGE));
+        assert_eq!(guard.pushing(), None);
+    }
+
+    #[test]
+    fn dwell_holds_the_push_until_it_is_over() {
+        let disp = display();
+        let mut guard = edge_guard(SwitchGuard {
+            dwell: 50,
+            ..Default::default()
+        });
+
+        assert!(!guard.allow(&disp, EDGE));
+        assert_eq!(guard.pushing(), Some(EDGE));
+
+        // asked for once per push
+        let left = guard.wake_in().unwrap();
+        assert!(left <= Duration::from_millis(50));
+        assert_eq!(guard.wake_in(), None);
+
+        thread::sleep(left + Duration::from_millis(10));
+        assert!(guard.allow(&disp, EDGE));
+    }
+
+    #[test]
+    fn leaving_the_edge_restarts_the_dwell() {
+        let disp = display();
+        let mut guard = edge_guard(SwitchGuard {
+            dwell: 50,
+            ..Default::default()
+        });
+
+        assert!(!guard.allow(&disp, EDGE));
+        thread::sleep(Duration::from_millis(60));
+        guard.leave();
+
+        assert!(!guard.allow(&disp, EDGE));
+        assert!(guard.wake_in().is_some());
+    }
+
+    #[test]
+    fn double_tap_needs_a_second_push_in_time() {
+        let disp = display();
+        let mut guard = edge_guard(SwitchGuard {
+            double_tap: 50,
+            ..Default::default()
+        });
+
+        assert!(!guard.allow(&disp, EDGE));
+        // waiting does not help a double tap; nothing to wake for
+        assert_eq!(guard.wake_in(), None);
+        guard.leave();
+        assert!(guard.allow(&disp, EDGE));
+
+        // too slow
+        assert!(!guard.allow(&disp, EDGE));
+        guard.leave();
+        thread::sleep(Duration::from_millis(60));
+        assert!(!guard.allow(&disp, EDGE));
+
+        // on another edge
+        guard.leave();
+        assert!(!guard.allow(&disp, ZoneDirection::VerticalDown));
+    }
+
+    #[test]
+    fn min_speed_takes_the_speed_the_edge_was_hit_with() {
+        let disp = display();
+        let mut guard = edge_guard(SwitchGuard {
+            min_speed: 1000.0,
+            ..Default::default()
+        });
+
+        // 5 counts over the whole speed window
+        guard.motion(5, 0);
+        assert!(!guard.allow(&disp, EDGE));
+        assert_eq!(guard.wake_in(), None);
+        guard.leave();
+
+        // 30 counts within a few ms
+        thread::sleep(Duration::from_millis(5));
+        guard.motion(30, 0);
+        assert!(guard.allow(&disp, EDGE));
+    }
+
+    #[test]
+    fn modifier_has_to_be_held() {
+        let disp = display();
+        let mut guard = edge_guard(SwitchGuard {
+            modifier: Some(ModifierKey::Ctrl),
+            ..Default::default()
+        });
+
+        assert!(!guard.allow(&disp, EDGE));
+
+        guard.key(Key::LControl, true);
+        assert!(guard.allow(&disp, EDGE));
+
+        guard.key(Key::LControl, false);
+        assert!(!guard.allow(&disp, EDGE));
+    }
+
+    #[test]
+    fn rules_override_the_default_per_edge() {
+        let disp = display();
+        let mut guard = EdgeGuard::new(
+            SwitchGuard {
+                modifier: Some(ModifierKey::Alt),
+                ..Default::default()
+            },
+            vec![GuardRule {
+                display: None,
+                edge: Some(ZoneDirection::VerticalDown),
+                guard: SwitchGuard::default(),
+            }],
+            Arc::new(RwLock::new(false)),
+        );
+
+        assert!(!guard.allow(&disp, EDGE));
+        guard.leave();
+        assert!(guard.allow(&disp, ZoneDirection::VerticalDown));
+    }
+
+    #[test]
+    fn lock_blocks_every_switch() {
+        let disp = display();
+        let lock = Arc::new(RwLock::new(true));
+        let mut guard = EdgeGuard::new(
+            SwitchGuard {
+                dwell: 50,
+                ..Default::default()
+            },
+            Vec::new(),
+            lock.clone(),
+        );
+
+        assert!(!guard.allow(&disp, EDGE));
+        assert_eq!(guard.wake_in(), None);
+
+        *lock.write().unwrap() = false;
+        assert!(!guard.allow(&disp, EDGE));
+        assert!(guard.wake_in().is_some());
+    }
+}
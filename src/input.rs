@@ -0,0 +1,284 @@
+use serde::{Deserialize, Serialize};
+use winit::event::{MouseScrollDelta, VirtualKeyCode};
+
+pub type ScanCode = u32;
+
+/* pixel deltas (e.g. trackpads) are reported in lines of this height */
+pub const PIXELS_PER_LINE: f32 = 20.0;
+
+/* platform-neutral key codes; second column is the winit key it is captured from */
+macro_rules! keys {
+    ($($key:ident => $code:ident),* $(,)?) => {
+        #[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        pub enum Key {
+            $($key,)*
+            Unknown,
+        }
+
+        impl From<VirtualKeyCode> for Key {
+            fn from(code: VirtualKeyCode) -> Self {
+                match code {
+                    $(VirtualKeyCode::$code => Key::$key,)*
+                    _ => Key::Unknown,
+                }
+            }
+        }
+    };
+}
+
+keys! {
+    A => A, B => B, C => C, D => D, E => E, F => F, G => G, H => H, I => I,
+    J => J, K => K, L => L, M => M, N => N, O => O, P => P, Q => Q, R => R,
+    S => S, T => T, U => U, V => V, W => W, X => X, Y => Y, Z => Z,
+    Num0 => Key0, Num1 => Key1, Num2 => Key2, Num3 => Key3, Num4 => Key4,
+    Num5 => Key5, Num6 => Key6, Num7 => Key7, Num8 => Key8, Num9 => Key9,
+    F1 => F1, F2 => F2, F3 => F3, F4 => F4, F5 => F5, F6 => F6,
+    F7 => F7, F8 => F8, F9 => F9, F10 => F10, F11 => F11, F12 => F12,
+    F13 => F13, F14 => F14, F15 => F15, F16 => F16, F17 => F17, F18 => F18,
+    F19 => F19, F20 => F20, F21 => F21, F22 => F22, F23 => F23, F24 => F24,
+    Escape => Escape,
+    PrintScreen => Snapshot,
+    ScrollLock => Scroll,
+    Pause => Pause,
+    Insert => Insert,
+    Delete => Delete,
+    Home => Home,
+    End => End,
+    PageUp => PageUp,
+    PageDown => PageDown,
+    Left => Left,
+    Up => Up,
+    Right => Right,
+    Down => Down,
+    Backspace => Back,
+    Enter => Return,
+    Space => Space,
+    Tab => Tab,
+    CapsLock => Capital,
+    NumLock => Numlock,
+    Numpad0 => Numpad0, Numpad1 => Numpad1, Numpad2 => Numpad2, Numpad3 => Numpad3,
+    Numpad4 => Numpad4, Numpad5 => Numpad5, Numpad6 => Numpad6, Numpad7 => Numpad7,
+    Numpad8 => Numpad8, Numpad9 => Numpad9,
+    NumpadAdd => NumpadAdd,
+    NumpadSubtract => NumpadSubtract,
+    NumpadMultiply => NumpadMultiply,
+    NumpadDivide => NumpadDivide,
+    NumpadDecimal => NumpadDecimal,
+    NumpadEnter => NumpadEnter,
+    NumpadEquals => NumpadEquals,
+    Apostrophe => Apostrophe,
+    Backslash => Backslash,
+    Comma => Comma,
+    Equals => Equals,
+    Grave => Grave,
+    LBracket => LBracket,
+    RBracket => RBracket,
+    Minus => Minus,
+    Period => Period,
+    Semicolon => Semicolon,
+    Slash => Slash,
+    IntlBackslash => OEM102,
+    LShift => LShift,
+    RShift => RShift,
+    LControl => LControl,
+    RControl => RControl,
+    LAlt => LAlt,
+    RAlt => RAlt,
+    LMeta => LWin,
+    RMeta => RWin,
+    Menu => Apps,
+    Kana => Kana,
+    Kanji => Kanji,
+    Convert => Convert,
+    NoConvert => NoConvert,
+    Yen => Yen,
+    Mute => Mute,
+    VolumeDown => VolumeDown,
+    VolumeUp => VolumeUp,
+    PlayPause => PlayPause,
+    MediaStop => MediaStop,
+    NextTrack => NextTrack,
+    PrevTrack => PrevTrack,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Modifiers {
+    pub shift: bool,
+    pub ctrl: bool,
+    pub alt: bool,
+    pub meta: bool,
+    #[serde(skip)]
+    held: u8, // a bit per left and right key; the flags above are set while either one is down
+}
+
+impl Modifiers {
+    pub fn update(&mut self, key: Key, pressed: bool) {
+        let bit = match key {
+            Key::LShift => 0,
+            Key::RShift => 1,
+            Key::LControl => 2,
+            Key::RControl => 3,
+            Key::LAlt => 4,
+            Key::RAlt => 5,
+            Key::LMeta => 6,
+            Key::RMeta => 7,
+            _ => return,
+        };
+
+        match pressed {
+            true => self.held |= 1 << bit,
+            false => self.held &= !(1 << bit),
+        }
+
+        self.shift = self.held & 0b11 != 0;
+        self.ctrl = self.held & 0b1100 != 0;
+        self.alt = self.held & 0b110000 != 0;
+        self.meta = self.held & 0b11000000 != 0;
+    }
+
+    pub fn has(&self, key: ModifierKey) -> bool {
+        match key {
+            ModifierKey::Shift => self.shift,
+            ModifierKey::Ctrl => self.ctrl,
+            ModifierKey::Alt => self.alt,
+            ModifierKey::Meta => self.meta,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ModifierKey {
+    Shift,
+    Ctrl,
+    Alt,
+    Meta,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyInput {
+    pub key: Key,
+    pub scan: ScanCode,
+    pub modifiers: Modifiers,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Button {
+    Left,
+    Right,
+    Middle,
+    Back,
+    Forward,
+}
+
+impl Button {
+    /* raw device button ids; X11 and windows share the same numbering */
+    #[cfg(not(target_os = "macos"))]
+    pub fn from_raw(id: u32) -> Option<Self> {
+        match id {
+            1 => Some(Button::Left),
+            2 => Some(Button::Middle),
+            3 => Some(Button::Right),
+            8 => Some(Button::Back),
+            9 => Some(Button::Forward),
+            _ => None,
+        }
+    }
+
+    #[cfg(target_os = "macos")]
+    pub fn from_raw(id: u32) -> Option<Self> {
+        match id {
+            0 => Some(Button::Left),
+            1 => Some(Button::Right),
+            2 => Some(Button::Middle),
+            3 => Some(Button::Back),
+            4 => Some(Button::Forward),
+            _ => None,
+        }
+    }
+}
+
+/* wheel movement in lines; positive x scrolls right, positive y scrolls up */
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+pub struct ScrollDelta {
+    pub x: f32,
+    pub y: f32,
+}
+
+impl From<MouseScrollDelta> for ScrollDelta {
+    fn from(delta: MouseScrollDelta) -> Self {
+        // winit reports the direction the content moves to
+        match delta {
+            MouseScrollDelta::LineDelta(x, y) => ScrollDelta { x: -x, y },
+            MouseScrollDelta::PixelDelta(pos) => ScrollDelta {
+                x: -pos.x as f32 / PIXELS_PER_LINE,
+                y: pos.y as f32 / PIXELS_PER_LINE,
+            },
+        }
+    }
+}
+
+/* collects fractional scroll deltas until they add up to whole wheel notches */
+#[derive(Debug, Default)]
+pub struct ScrollAccumulator {
+    remain: ScrollDelta,
+}
+
+impl ScrollAccumulator {
+    pub fn push(&mut self, delta: ScrollDelta) -> (i32, i32) {
+        self.remain.x += delta.x;
+        self.remain.y += delta.y;
+
+        let x = self.remain.x.trunc();
+        let y = self.remain.y.trunc();
+
+        self.remain.x -= x;
+        self.remain.y -= y;
+
+        (x as i32, y as i32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scroll_fractions_add_up() {
+        let mut acc = ScrollAccumulator::default();
+        let tick = ScrollDelta { x: -0.25, y: 0.5 };
+
+        assert_eq!(acc.push(tick), (0, 0));
+        assert_eq!(acc.push(tick), (0, 1));
+        assert_eq!(acc.push(tick), (0, 0));
+        assert_eq!(acc.push(tick), (-1, 1));
+    }
+
+    #[test]
+    fn modifiers_stay_held_until_both_sides_are_released() {
+        let mut modifiers = Modifiers::default();
+
+        modifiers.update(Key::LShift, true);
+        modifiers.update(Key::RShift, true);
+        modifiers.update(Key::LShift, false);
+        assert!(modifiers.has(ModifierKey::Shift));
+
+        modifiers.update(Key::RShift, false);
+        assert!(!modifiers.has(ModifierKey::Shift));
+
+        // each modifier on its own
+        modifiers.update(Key::RControl, true);
+        modifiers.update(Key::LAlt, true);
+        modifiers.update(Key::RMeta, true);
+        modifiers.update(Key::LAlt, false);
+        assert!(modifiers.has(ModifierKey::Ctrl));
+        assert!(!modifiers.has(ModifierKey::Alt));
+        assert!(modifiers.has(ModifierKey::Meta));
+        assert!(!modifiers.has(ModifierKey::Shift));
+
+        // releasing a key that was never seen going down changes nothing
+        modifiers.update(Key::LControl, false);
+        modifiers.update(Key::A, false);
+        assert!(modifiers.has(ModifierKey::Ctrl));
+    }
+}
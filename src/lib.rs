@@ -1,13 +1,23 @@
 mod client;
 mod comm;
 mod display;
+mod guard;
+mod input;
 mod server;
+mod sink;
+mod source;
+mod tls;
 mod utils;
 
 pub use client::*;
 pub use comm::*;
 pub use display::*;
+pub use guard::*;
+pub use input::*;
 pub use server::*;
+pub use sink::*;
+pub use source::*;
+pub use tls::*;
 pub use utils::*;
 
 pub const PORT: u16 = 2426;
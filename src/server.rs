@@ -1,103 +1,250 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fs;
-use std::io::{Error, ErrorKind::*};
-use std::mem;
-use std::net::TcpListener;
+use std::io::{stdin, stdout, Error, ErrorKind::*, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
 use std::path::PathBuf;
 use std::sync::{
-    mpsc::{channel, Receiver, Sender, TryRecvError},
-    Arc, RwLock,
+    mpsc::{channel, Receiver, Sender},
+    Arc, Mutex, RwLock,
 };
 use std::thread;
+use std::time::{Duration, Instant};
 
 use bincode::deserialize;
-use display_info::DisplayInfo;
-use mouce::common::MouseEvent;
-use mouce::Mouse;
+use ring::signature::{UnparsedPublicKey, ED25519};
+use serde::{Deserialize, Serialize};
 
 use crate::client::*;
 use crate::comm::*;
 use crate::display::*;
 use crate::*;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Server {
     clients: Arc<RwLock<HashMap<Cid, Client>>>,
     displays: Arc<RwLock<HashMap<Did, Display>>>,
     disp_ids: Arc<RwLock<AssignedDisplays>>,
     focus: Arc<RwLock<Did>>,
     current: Arc<RwLock<Cid>>,
+    pointer: Arc<RwLock<(i32, i32)>>, // global layout coordinates while a remote display has focus
+    lock: Arc<RwLock<bool>>,          // cursor held on its current display
+    held: Arc<Mutex<HeldInput>>,      // forwarded to the client with focus and not released yet
+    tls: Arc<rustls::ServerConfig>,
+    pairing: Arc<RwLock<Option<Pairing>>>, // set while in pairing mode
+    config: ServerConfig,
+}
+
+/* one-time code a new client has to present */
+#[derive(Debug, Clone)]
+struct Pairing {
+    code: String,
+    expires: Instant,
+    attempts: u32,
+}
+
+/* the server cursor, placed from the threads of clients the cursor leaves */
+type SharedCursor = Arc<Mutex<Box<dyn Cursor>>>;
+
+/* a client that stalls the handshake longer than this before it is authenticated is dropped */
+const HANDSHAKE_TIMEOUT: Duration = match cfg!(test) {
+    true => Duration::from_millis(200), // tests outwait it
+    false => Duration::from_secs(10),
+};
+
+const PAIRING_TIMEOUT: Duration = Duration::from_secs(300);
+const PAIRING_ATTEMPTS: u32 = 3;
+
+/* server_config.json; every field is optional */
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ServerConfig {
+    #[serde(default)]
+    pub edge_mapping: EdgeMapping,
+    #[serde(default)]
+    pub links: Vec<DisplayLink>,
+    #[serde(default)]
+    pub wrap: WrapMode,
+    #[serde(default)]
+    pub corner: CornerMode,
+    #[serde(default = "default_corner_size")]
+    pub corner_size: i32,
+    #[serde(default)]
+    pub guard: SwitchGuard,
+    #[serde(default)]
+    pub guards: Vec<GuardRule>,
+    #[serde(default = "default_lock_key")]
+    pub lock_key: Vec<Key>, // chord toggling the cursor lock; empty to disable
+    #[serde(default = "default_max_clients")]
+    pub max_clients: usize,
+    #[serde(default = "default_gap_tolerance")]
+    pub gap_tolerance: i32, // px between displays reported as a likely mistake; 0 to disable
+}
+
+fn default_corner_size() -> i32 {
+    16
+}
+
+fn default_lock_key() -> Vec<Key> {
+    vec![Key::ScrollLock]
+}
+
+fn default_max_clients() -> usize {
+    16
+}
+
+fn default_gap_tolerance() -> i32 {
+    GAP_TOLERANCE
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            edge_mapping: EdgeMapping::default(),
+            links: Vec::new(),
+            wrap: WrapMode::default(),
+            corner: CornerMode::default(),
+            corner_size: default_corner_size(),
+            guard: SwitchGuard::default(),
+            guards: Vec::new(),
+            lock_key: default_lock_key(),
+            max_clients: default_max_clients(),
+            gap_tolerance: default_gap_tolerance(),
+        }
+    }
+}
+
+impl ServerConfig {
+    pub fn crossing(&self) -> Crossing {
+        Crossing {
+            mapping: self.edge_mapping,
+            corner: self.corner,
+            corner_size: self.corner_size,
+        }
+    }
 }
 
 impl Server {
-    pub fn new(display_scale: f32) -> Result<Server, Error> {
+    pub fn new(source: &dyn DisplaySource) -> Result<Server, Error> {
         // mkdir -p
         fs::create_dir_all(config_dir!("server"))?;
 
-        let mut disp: Vec<Display> = DisplayInfo::all()
-            .expect("[ERR] failed to get system displays")
-            .into_iter()
-            .map(|x| Display::from(x, SERVER_CID, display_scale))
-            .collect();
+        let config = get_server_config(config_dir!("server").join("server_config.json"))?;
+
+        let mut disp = source.displays(SERVER_CID)?;
 
-        if disp.len() == 0 {
+        if disp.is_empty() {
             return Err(Error::new(NotFound, "[ERR] system display not found"));
         }
 
+        let mut handles = DisplayHandles::default();
+        handles.assign_all(&mut disp)?;
+
         let system = disp.iter().map(|x| x.id).collect();
         let focus = Arc::new(RwLock::new(
             disp.iter().find(|x| x.is_primary).unwrap_or(&disp[0]).id,
         ));
-        let displays = Arc::new(RwLock::new(
-            disp.iter().map(|x| (x.id, x.clone())).collect(),
-        ));
 
         let mut dummy = disp.clone();
 
         /* create warpzone twice with reverse order to write correctly in disp, not dummy */
-        if let Err(_) = create_warpzones(&mut disp, &mut dummy, true) {
+        if create_warpzones(&mut disp, &mut dummy, true).is_err() {
             return Err(Error::new(InvalidData, "[ERR] system display init failed"));
         };
 
-        if let Err(_) = create_warpzones(&mut dummy, &mut disp, true) {
+        if create_warpzones(&mut dummy, &mut disp, true).is_err() {
             return Err(Error::new(InvalidData, "[ERR] system display init failed"));
         };
 
+        let mut layout = disp.into_iter().map(|x| (x.id, x)).collect();
+        arrange(&mut layout, &config);
+
+        let displays = Arc::new(RwLock::new(layout));
+
         Ok(Server {
             clients: Arc::new(RwLock::new(HashMap::new())),
             displays,
             disp_ids: Arc::new(RwLock::new(AssignedDisplays {
                 system,
                 client: Vec::new(),
+                handles,
             })),
             focus,
             current: Arc::new(RwLock::new(SERVER_CID)),
+            pointer: Arc::new(RwLock::new((0, 0))),
+            lock: Arc::new(RwLock::new(false)),
+            held: Arc::new(Mutex::new(HeldInput::default())),
+            tls: server_tls_config()?,
+            pairing: Arc::new(RwLock::new(None)),
+            config,
         })
     }
 
+    /* let the next client that enters the code in time add itself to the authorized clients */
+    pub fn pair(&self) {
+        let code = format!("{:06}", rand::random::<u32>() % 1_000_000);
+
+        println!(
+            "[INF] pairing code: {} (valid for {} minutes)",
+            code,
+            PAIRING_TIMEOUT.as_secs() / 60
+        );
+        println!("[INF] enter it on the new client started with --pair");
+
+        *self.pairing.write().unwrap() = Some(Pairing {
+            code,
+            expires: Instant::now() + PAIRING_TIMEOUT,
+            attempts: 0,
+        });
+    }
+
     pub fn start(&self, authorized: PathBuf) {
-        /* message exchange channels */
-        let (tx, rx1) = channel::<Message>();
-        let (tx1, rx) = channel::<Message>();
+        /* capture system input; blocks until the event loop exits */
+        match SystemSource::new() {
+            Ok(mut source) => self.start_with(&mut source, authorized),
+            Err(e) => eprintln!("[ERR] input capture failed: {}", e),
+        }
+    }
 
-        let current = self.current.clone();
-        let clients = self.clients.clone();
-        let displays = self.displays.clone();
-        let disp_ids = self.disp_ids.clone();
+    pub fn start_with<S: InputSource>(&self, source: &mut S, authorized: PathBuf) {
+        match TcpListener::bind(("0.0.0.0", PORT)) {
+            Ok(listener) => self.serve(source, authorized, listener),
+            Err(e) => eprintln!("[ERR] TCP binding failed: {}", e),
+        }
+    }
+
+    /* start_with on a listener of the caller's choosing */
+    pub fn serve<S: InputSource>(
+        &self,
+        source: &mut S,
+        authorized: PathBuf,
+        listener: TcpListener,
+    ) {
+        /* message exchange channel */
+        let (tx, rx) = channel::<Message>();
+
+        let server = self.clone();
+        let requests = tx.clone();
+        let cursor = Arc::new(Mutex::new(source.cursor()));
 
         /* spawn tcp handler thread */
         let thread = thread::spawn(move || {
-            handle_client(current, clients, displays, disp_ids, authorized, tx1, rx1);
+            handle_client(server, listener, authorized, requests, rx, cursor);
         });
 
-        let mut mouce = Mouse::new();
+        if let Err(e) = self.run(source, tx) {
+            eprintln!("[ERR] input capture failed: {}", e);
+        }
+
+        if let Err(e) = thread.join() {
+            eprintln!("[ERR] transceiver thread panicked: {:?}", e);
+        }
+    }
 
+    /* route input events from the source into messages for the focused client */
+    pub fn run<S: InputSource>(&self, source: &mut S, tx: Sender<Message>) -> Result<(), Error> {
         /* find out the current display */
         let disp_ids = self.disp_ids.clone();
         let displays = self.displays.clone();
-        let (x, y) = mouce
-            .get_position()
-            .expect("[ERR] cannot get cursor position");
+        let (x, y) = source.position()?;
 
         {
             let display_map = displays.read().unwrap();
@@ -105,286 +252,2007 @@ impl Server {
             for disp in disp_ids.read().unwrap().system.iter() {
                 let d = display_map.get(disp).unwrap();
 
-                if x > d.x && x < (d.x + d.width) && y > d.y && y < (d.y + d.height) {
+                let (gx, gy) = d.system_to_global(x, y);
+
+                if d.contains(gx, gy) {
                     *self.focus.write().unwrap() = d.id;
                     break;
                 }
             }
         }
 
-        /* listen mouse events */
+        /* listen input events */
         let focus = self.focus.clone();
         let current = self.current.clone();
+        let pointer = self.pointer.clone();
+        let crossing = self.config.crossing();
+        let mapping = crossing.mapping;
+        let lock = self.lock.clone();
+        let held = self.held.clone();
+        let lock_key = self.config.lock_key.clone();
+        let mut pressed = HashSet::new();
+        let mut swallowed = HashSet::new(); // pressed here, so released here too
+        let mut guard = EdgeGuard::new(
+            self.config.guard,
+            self.config.guards.clone(),
+            self.lock.clone(),
+        );
+        let mut cursor = source.cursor();
+        let waker = Arc::new(Mutex::new(source.cursor()));
+
+        // OS pixels per millimeter of the server display the cursor left from
+        let mut home = {
+            let disps = displays.read().unwrap();
+            let disp = disps.get(&*focus.read().unwrap()).unwrap();
 
-        let hook = mouce.hook(Box::new(move |e| {
-            let (x, y) = if let MouseEvent::AbsoluteMove(x, y) = e {
-                (*x, *y)
-            } else {
-                return;
+            disp.pixels_per_mm() * disp.pixel_ratio as f64
+        };
+        let mut remain = (0.0, 0.0);
+        let mut last_motion = (0, 0);
+        let mut position = (x, y);
+
+        source.run(Box::new(move |event| {
+            let send = |msg| {
+                if let Err(e) = tx.send(msg) {
+                    eprintln!("[ERR] mpsc tx failed: {}", e);
+                }
+            };
+
+            let forward = |action| {
+                // forward only while a remote display has focus
+                if *current.read().unwrap() == SERVER_CID {
+                    return;
+                }
+
+                held.lock().unwrap().update(&action);
+
+                send(Message {
+                    disp: *focus.read().unwrap(),
+                    action,
+                    x: 0,
+                    y: 0,
+                });
+            };
+
+            // a push waiting for its dwell is looked at again once the dwell is over
+            let wake_later = |guard: &mut EdgeGuard| {
+                if let Some(left) = guard.wake_in() {
+                    let waker = waker.clone();
+
+                    thread::spawn(move || {
+                        thread::sleep(left);
+
+                        if let Err(e) = waker.lock().unwrap().wake() {
+                            eprintln!("[ERR] cursor wake failed: {}", e);
+                        }
+                    });
+                }
             };
 
-            /* check if we are in warpzone */
+            let wake = matches!(event, InputEvent::Wake);
+
+            let (x, y, motion) = match event {
+                InputEvent::Position(x, y) => {
+                    position = (x, y);
+                    (x, y, false)
+                }
+                InputEvent::Motion(dx, dy) => (dx, dy, true),
+                InputEvent::Wake => match (*current.read().unwrap() == SERVER_CID, guard.pushing())
+                {
+                    // the physical cursor rests where it was
+                    (true, _) => (position.0, position.1, false),
+                    // one more step into the edge of the remote display
+                    (false, Some(edge)) => match edge {
+                        ZoneDirection::HorizontalLeft => (-1, 0, true),
+                        ZoneDirection::HorizontalRight => (1, 0, true),
+                        ZoneDirection::VerticalUp => (0, -1, true),
+                        ZoneDirection::VerticalDown => (0, 1, true),
+                    },
+                    (false, None) => return,
+                },
+                InputEvent::Key(input, true) => {
+                    guard.key(input.key, true);
+
+                    if swallowed.contains(&input.key) {
+                        return;
+                    }
+
+                    // the key completing the lock chord stays here
+                    if pressed.insert(input.key)
+                        && lock_key.contains(&input.key)
+                        && lock_key.iter().all(|x| pressed.contains(x))
+                    {
+                        swallowed.insert(input.key);
+
+                        let locked = !*lock.read().unwrap();
+                        return set_lock(&lock, locked, &tx);
+                    }
+
+                    return forward(Action::KeyDown(input));
+                }
+                InputEvent::Key(input, false) => {
+                    guard.key(input.key, false);
+                    pressed.remove(&input.key);
+
+                    // the client never saw it go down
+                    if swallowed.remove(&input.key) {
+                        return;
+                    }
+
+                    return forward(Action::KeyUp(input));
+                }
+                InputEvent::Button(button, true) => return forward(Action::ButtonDown(button)),
+                InputEvent::Button(button, false) => return forward(Action::ButtonUp(button)),
+                InputEvent::Scroll(delta) => return forward(Action::Scroll(delta)),
+            };
+
+            // raw motion arrives right before the position it leads to
+            if motion && !wake {
+                last_motion = (x, y);
+                guard.motion(x, y);
+            }
+
             let mut cur_did = focus.write().unwrap();
             let mut current = current.write().unwrap();
+            let mut pointer = pointer.write().unwrap();
 
             let disps = displays.read().unwrap();
-            let cur = disps.get(&*cur_did).unwrap();
-
-            let mut warp_point: Option<(i32, i32)> = None;
-
-            for wz in cur.warpzones.iter() {
-                match wz.direction {
-                    ZoneDirection::HorizontalLeft => {
-                        if y >= wz.start - MARGIN && y <= wz.end + MARGIN && x <= cur.x + MARGIN {
-                            *cur_did = wz.to;
-                            let to = disps.get(&*cur_did).unwrap();
-                            *current = to.owner;
-                            warp_point = Some((x - to.x, y - to.y));
-                            break;
+
+            // true when the cursor does not simply cross a shared edge
+            let mut jump = false;
+
+            let (to, (x, y)) = match (motion, *current == SERVER_CID) {
+                /* the physical cursor drives the server displays */
+                (false, true) => {
+                    // the OS moves the cursor between our own displays without telling
+                    let cur = display_at(&disps, SERVER_CID, x, y)
+                        .unwrap_or_else(|| disps.get(&*cur_did).unwrap());
+
+                    *cur_did = cur.id;
+
+                    let (x, y) = cur.system_to_global(x, y);
+
+                    // no go
+                    let wz = match cur.find_warpzone(x, y, last_motion, &crossing) {
+                        Some(wz) => wz,
+                        None => {
+                            if cur.find_warpzone(x, y, (0, 0), &crossing).is_none() {
+                                guard.leave();
+                            }
+                            return;
                         }
+                    };
+
+                    if !guard.allow(cur, wz.direction) {
+                        return wake_later(&mut guard);
                     }
-                    ZoneDirection::HorizontalRight => {
-                        if y >= wz.start - MARGIN
-                            && y <= wz.end + MARGIN
-                            && x >= (cur.x + cur.width as i32) - MARGIN
-                        {
-                            *cur_did = wz.to;
-                            let to = disps.get(&*cur_did).unwrap();
-                            *current = to.owner;
-                            warp_point = Some((x - to.x, y - to.y));
-                            break;
+
+                    let to = disps.get(&wz.to).unwrap();
+
+                    home = cur.pixels_per_mm() * cur.pixel_ratio as f64;
+                    remain = (0.0, 0.0);
+                    jump = wz.kind != ZoneKind::Adjacent;
+
+                    (to, cur.cross(wz, to, x, y, mapping))
+                }
+                /* relative motion drives the virtual cursor on remote displays */
+                (true, false) => {
+                    let cur = match disps.get(&*cur_did) {
+                        Some(cur) => cur,
+                        None => {
+                            // the client went away with the display; back to the server
+                            let disp = disps
+                                .values()
+                                .filter(|d| d.owner == SERVER_CID)
+                                .max_by_key(|d| d.is_primary)
+                                .unwrap();
+
+                            let (width, height) = disp.size();
+                            let (x, y) =
+                                disp.global_to_system(disp.x + width / 2, disp.y + height / 2);
+
+                            *cur_did = disp.id;
+                            *current = SERVER_CID;
+
+                            if let Err(e) = cursor.confine(false).and_then(|_| cursor.warp(x, y)) {
+                                eprintln!("[ERR] cursor release failed: {}", e);
+                            }
+
+                            return;
                         }
+                    };
+
+                    let (dx, dy) = if wake {
+                        (x, y)
+                    } else {
+                        // the same hand motion covers the same physical distance on every display
+                        let ratio = cur.pixels_per_mm() / home;
+
+                        remain.0 += x as f64 * ratio;
+                        remain.1 += y as f64 * ratio;
+
+                        let (dx, dy) = (remain.0.trunc(), remain.1.trunc());
+                        remain.0 -= dx;
+                        remain.1 -= dy;
+
+                        (dx as i32, dy as i32)
+                    };
+
+                    let target = track(&disps, *cur_did, *pointer, dx, dy, crossing, &mut guard);
+                    wake_later(&mut guard);
+
+                    match target {
+                        Some(target) => target,
+                        None => return,
                     }
-                    ZoneDirection::VerticalUp => {
-                        if x >= wz.start - MARGIN && x <= wz.end + MARGIN && y <= cur.y + MARGIN {
-                            *cur_did = wz.to;
-                            let to = disps.get(&*cur_did).unwrap();
-                            *current = to.owner;
-                            warp_point = Some((x - to.x, y - to.y));
-                            break;
-                        }
+                }
+                _ => return,
+            };
+
+            release_held(&held, *cur_did, *current, to.owner, &tx);
+
+            *cur_did = to.id;
+            *current = to.owner;
+            *pointer = (x, y);
+
+            // every machine gets points in its own OS coordinates
+            let (x, y) = to.global_to_system(x, y);
+
+            // the OS moves the cursor between our own displays unless they are not adjacent
+            if to.owner == SERVER_CID {
+                if motion {
+                    // back from a remote display; hand the cursor back to local apps
+                    if let Err(e) = cursor.confine(false).and_then(|_| cursor.warp(x, y)) {
+                        eprintln!("[ERR] cursor release failed: {}", e);
                     }
-                    ZoneDirection::VerticalDown => {
-                        if x >= wz.start - MARGIN
-                            && x <= wz.end + MARGIN
-                            && y >= (cur.y + cur.height as i32) - MARGIN
-                        {
-                            *cur_did = wz.to;
-                            let to = disps.get(&*cur_did).unwrap();
-                            *current = to.owner;
-                            warp_point = Some((x - to.x, y - to.y));
-                            break;
-                        }
+                } else if jump {
+                    if let Err(e) = cursor.warp(x, y) {
+                        eprintln!("[ERR] cursor warp failed: {}", e);
                     }
                 }
-            }
 
-            // no go
-            if warp_point.is_none() {
                 return;
             }
 
-            /* warp sequence begin */
-            let (x, y) = warp_point.unwrap();
-
             // transmit warp point
-            if let Err(e) = tx.send(Message {
-                disp: *cur_did,
+            send(Message {
+                disp: to.id,
                 action: Action::Warp,
                 x,
                 y,
-            }) {
-                eprintln!("[ERR] mpsc tx failed: {}", e);
+            });
+
+            // hold the local cursor while the remote display has focus
+            if !motion {
+                if let Err(e) = cursor.confine(true) {
+                    eprintln!("[ERR] cursor confinement failed: {}", e);
+                }
             }
+        }))
+    }
+}
+
+/* display of `owner` under (x, y) in the OS coordinates of that machine */
+fn display_at(displays: &HashMap<Did, Display>, owner: Cid, x: i32, y: i32) -> Option<&Display> {
+    displays.values().filter(|d| d.owner == owner).find(|d| {
+        let (x, y) = d.system_to_global(x, y);
+        d.contains(x, y)
+    })
+}
 
-            // warp
-            // TODO: winit
-        }));
+/* next virtual cursor position after moving (dx, dy) from `pointer` on display `focus` */
+fn track<'a>(
+    displays: &'a HashMap<Did, Display>,
+    focus: Did,
+    pointer: (i32, i32),
+    dx: i32,
+    dy: i32,
+    crossing: Crossing,
+    guard: &mut EdgeGuard,
+) -> Option<(&'a Display, (i32, i32))> {
+    let cur = displays.get(&focus)?;
+
+    if (dx, dy) == (0, 0) {
+        return None;
+    }
+
+    // the virtual cursor stops at the edges just like the physical one
+    let (width, height) = cur.size();
 
-        if let Err(e) = hook {
-            eprintln!("[ERR] event hook failed: {}", e);
+    let x = (pointer.0 + dx).clamp(cur.x, cur.x + width - 1);
+    let y = (pointer.1 + dy).clamp(cur.y, cur.y + height - 1);
+
+    // only pass through while pushing against the edge
+    let wz = match cur.find_warpzone(x, y, (dx, dy), &crossing) {
+        Some(wz) => guard.allow(cur, wz.direction).then_some(wz),
+        None => {
+            if cur.find_warpzone(x, y, (0, 0), &crossing).is_none() {
+                guard.leave();
+            }
+            None
         }
+    };
 
-        if let Err(e) = thread.join() {
-            eprintln!("[ERR] transceiver thread panicked: {:?}", e);
+    match wz {
+        Some(wz) => {
+            let to = displays.get(&wz.to)?;
+            Some((to, cur.cross(wz, to, x, y, crossing.mapping)))
+        }
+        None if (x, y) == pointer => None,
+        None => Some((cur, (x, y))),
+    }
+}
+
+/* focus moves from display `from` of `owner` to a machine `to`; what is held there is let go */
+fn release_held(held: &Mutex<HeldInput>, from: Did, owner: Cid, to: Cid, tx: &Sender<Message>) {
+    if owner == to {
+        return;
+    }
+
+    for action in held.lock().unwrap().release() {
+        let msg = Message {
+            disp: from,
+            action,
+            x: 0,
+            y: 0,
+        };
+
+        if let Err(e) = tx.send(msg) {
+            eprintln!("[ERR] mpsc tx failed: {}", e);
+        }
+    }
+}
+
+/* every client display learns its ways out, links and wraps included, from the server's layout */
+fn send_exits(layout: &HashMap<Did, Display>, tx: &Sender<Message>) {
+    for disp in layout.values().filter(|x| x.owner != SERVER_CID) {
+        let msg = Message {
+            disp: disp.id,
+            action: Action::Exits(disp.warpzones.clone()),
+            x: 0,
+            y: 0,
+        };
+
+        if let Err(e) = tx.send(msg) {
+            eprintln!("[ERR] mpsc tx failed: {}", e);
         }
     }
 }
 
+/* warpzones that do not come from pixel adjacency */
+fn arrange(layout: &mut HashMap<Did, Display>, config: &ServerConfig) {
+    apply_links(layout, &config.links);
+    apply_wrap(layout, config.wrap);
+}
+
+/* hold the cursor on its current display or let it go; every client gets told */
+fn set_lock(lock: &RwLock<bool>, locked: bool, tx: &Sender<Message>) {
+    *lock.write().unwrap() = locked;
+
+    if locked {
+        println!("[INF] cursor locked to the current display");
+    } else {
+        println!("[INF] cursor unlocked");
+    }
+
+    let msg = Message {
+        disp: 0, // every client
+        action: Action::Lock(locked),
+        x: 0,
+        y: 0,
+    };
+
+    if let Err(e) = tx.send(msg) {
+        eprintln!("[ERR] mpsc tx failed: {}", e);
+    }
+}
+
 fn handle_client(
-    current: Arc<RwLock<Cid>>,
-    clients: Arc<RwLock<HashMap<Cid, Client>>>,
-    mut displays: Arc<RwLock<HashMap<Did, Display>>>,
-    disp_ids: Arc<RwLock<AssignedDisplays>>,
-    authorized: PathBuf,
+    server: Server,
+    tcp: TcpListener,
+    file: PathBuf,
     tx: Sender<Message>,
     rx: Receiver<Message>,
+    cursor: SharedCursor,
 ) {
     /* spawn transceiver thread */
-    let client_list = clients.clone();
+    let transceiver = server.clone();
 
     thread::spawn(move || {
-        transceive(current, client_list, tx, rx);
+        transceive(transceiver, rx);
     });
 
     /* get authorized client list */
-    let authorized =
-        get_authorized_clients(authorized).expect("[ERR] failed to read client config");
+    let authorized = Arc::new(RwLock::new(
+        get_authorized_clients(file.clone()).expect("[ERR] failed to read client config"),
+    ));
+
+    /* start handshaking with clients */
+    for stream in tcp.incoming().filter_map(Result::ok) {
+        let server = server.clone();
+        let file = file.clone();
+        let authorized = authorized.clone();
+        let tx = tx.clone();
+        let cursor = cursor.clone();
+
+        // side by side; a stalled client or a pairing waiting for the operator holds up nobody
+        thread::spawn(move || handshake(server, stream, &file, &authorized, tx, cursor));
+    }
+}
+
+fn handshake(
+    server: Server,
+    stream: TcpStream,
+    file: &PathBuf,
+    authorized: &RwLock<HashMap<Cid, Vec<u8>>>,
+    tx: Sender<Message>,
+    cursor: SharedCursor,
+) {
+    let owner = server.clone();
+
+    let Server {
+        clients,
+        displays,
+        disp_ids,
+        lock,
+        tls,
+        pairing,
+        config,
+        ..
+    } = server;
+
+    // the peer may have reset the connection already
+    let ip = match stream.peer_addr() {
+        Ok(ip) => ip,
+        Err(e) => {
+            eprintln!("[ERR] client connection lost: {}", e);
+            return;
+        }
+    };
+
+    if let Err(e) = stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT)) {
+        eprintln!("[ERR] client {} handshake failed: {}", ip, e);
+        return;
+    }
 
-    let tcp = TcpListener::bind(("0.0.0.0", PORT)).expect("[ERR] TCP binding failed");
+    let mut stream = match SecureStream::accept(stream, &tls) {
+        Ok(stream) => stream,
+        Err(e) => {
+            eprintln!("[ERR] client {} tls handshake failed: {}", ip, e);
+            return;
+        }
+    };
+
+    /* read hello from remote client */
+    let mut buffer = Vec::new();
+
+    if let Err(e) = tcp_read(&mut stream, &mut buffer) {
+        eprintln!("[ERR] client {} handshake failed: {}", ip, e);
+        return;
+    };
+
+    // the version comes first in every hello; the rest may differ between versions
+    match deserialize::<u32>(&buffer) {
+        Ok(PROTOCOL_VERSION) => {}
+        Ok(version) => {
+            let reason = format!(
+                "client speaks protocol version {}, server speaks version {}",
+                version, PROTOCOL_VERSION
+            );
+            refuse(
+                &mut stream,
+                &reason,
+                HandshakeStatus::HandshakeErr(reason.clone()),
+            );
+            return;
+        }
+        Err(e) => {
+            reject(&mut stream, Rejection::Malformed(e.to_string()));
+            return;
+        }
+    }
 
-    /* start handshaking with client */
-    for mut stream in tcp.incoming().filter_map(Result::ok) {
-        let ip = stream.peer_addr().unwrap();
+    let hello: Hello = match deserialize(&buffer) {
+        Ok(hello) => hello,
+        Err(e) => {
+            reject(&mut stream, Rejection::Malformed(e.to_string()));
+            return;
+        }
+    };
+
+    let cid = hello.cid;
 
-        /* read cid from remote client */
-        let mut buffer = vec![0u8; mem::size_of::<Cid>()];
+    /* a cid alone proves nothing; the client signs fresh nonces with the key on file.
+     * known or not, every cid is challenged the same so the replies tell nothing about it */
+    let challenge = Challenge {
+        nonce: rand::random(),
+    };
 
-        if let Err(e) = tcp_read(&mut stream, &mut buffer) {
+    let binding = match stream.binding() {
+        Ok(binding) => binding,
+        Err(e) => {
             eprintln!("[ERR] client {} handshake failed: {}", ip, e);
-            continue;
-        };
+            return;
+        }
+    };
+    let payload = challenge_payload(cid, &hello.nonce, &challenge.nonce, &binding);
+
+    if let Err(e) = tcp_write(&mut stream, HandshakeStatus::HandshakeChallenge(challenge)) {
+        eprintln!("[ERR] client {} handshake failed: {}", ip, e);
+        return;
+    };
+
+    if let Err(e) = tcp_read(&mut stream, &mut buffer) {
+        eprintln!("[ERR] client {} handshake failed: {}", ip, e);
+        return;
+    };
+
+    let response: Response = match deserialize(&buffer) {
+        Ok(response) => response,
+        Err(e) => {
+            reject(&mut stream, Rejection::Malformed(e.to_string()));
+            return;
+        }
+    };
+
+    // a pairing code is checked whether the cid is known or not
+    if let Some(request) = &hello.pairing {
+        if let Err(reason) = check_pairing(&pairing, &request.code) {
+            reject(&mut stream, reason);
+            return;
+        }
+    }
 
-        let cid = deserialize(&buffer).unwrap();
+    // unknown clients only get in with the pairing code and the key they paired with
+    let known = authorized.read().unwrap().get(&cid).cloned();
+    let key = known
+        .clone()
+        .or_else(|| hello.pairing.as_ref().map(|x| x.key.clone()));
 
-        // reject unknown client
-        if !authorized.contains(&cid) {
-            if let Err(e) = tcp_write(&mut stream, 0) {
-                eprintln!("[ERR] client {} handshake failed: {}", ip, e);
-                continue;
-            };
+    let verified = key.as_ref().is_some_and(|key| {
+        UnparsedPublicKey::new(&ED25519, key)
+            .verify(&payload, &response.signature)
+            .is_ok()
+    });
+
+    let key = match (verified, key) {
+        (true, Some(key)) => key,
+        _ => {
+            reject(&mut stream, Rejection::Unauthorized(cid));
+            return;
         }
+    };
 
-        // transmit display counts to client
-        if let Err(e) = tcp_write(&mut stream, displays.read().unwrap().len() as u32) {
-            eprintln!("[ERR] client {} handshake failed: {}", ip, e);
-            continue;
-        };
+    // the code alone is not enough; somebody at the server has a look at the new client
+    if known.is_none() && !confirm_pairing(cid, ip, &key) {
+        reject(&mut stream, Rejection::Declined);
+        return;
+    }
 
-        // transmit current displays
-        {
-            let disp = displays.read().unwrap();
+    {
+        let clients = clients.read().unwrap();
 
-            if let Err(e) = tcp_write(&mut stream, disp.clone()) {
-                eprintln!("[ERR] client {} handshake failed: {}", ip, e);
-                continue;
-            };
+        if clients.contains_key(&cid) {
+            reject(&mut stream, Rejection::DuplicateCid(cid));
+            return;
         }
 
-        // receive display attach request
-        if let Err(e) = tcp_read(&mut stream, &mut buffer) {
-            eprintln!("[ERR] client {} handshake failed: {}", ip, e);
-            continue;
-        };
+        if clients.len() >= config.max_clients {
+            reject(&mut stream, Rejection::ServerFull(config.max_clients));
+            return;
+        }
+    }
 
-        let mut client_disp: Vec<Display> = deserialize(&buffer).unwrap();
+    if let Err(e) = tcp_write(&mut stream, HandshakeStatus::HandshakeOk) {
+        eprintln!("[ERR] client {} handshake failed: {}", ip, e);
+        return;
+    };
+
+    let capabilities = negotiate(&hello.capabilities);
+
+    // transmit current displays
+    let welcome = Welcome {
+        version: PROTOCOL_VERSION,
+        capabilities: capabilities.clone(),
+        displays: displays.read().unwrap().clone(),
+    };
+
+    if let Err(e) = tcp_write(&mut stream, welcome) {
+        eprintln!("[ERR] client {} handshake failed: {}", ip, e);
+        return;
+    };
+
+    // somebody may be placing the client displays by hand now; connected clients stay silent too
+    if let Err(e) = stream.set_read_timeout(None) {
+        eprintln!("[ERR] client {} handshake failed: {}", ip, e);
+        return;
+    }
 
-        // update warpzones for new displays
-        let new = match create_warpzones_hashmap(&mut displays, &mut client_disp) {
-            Ok(new) => new,
-            Err(e) => {
-                eprintln!("[ERR] invalid request from client {} : {}", ip, e);
-                continue;
-            }
+    // receive display attach request
+    if let Err(e) = tcp_read(&mut stream, &mut buffer) {
+        eprintln!("[ERR] client {} handshake failed: {}", ip, e);
+        return;
+    };
+
+    let mut client_disp: Vec<Display> = match deserialize(&buffer) {
+        Ok(disp) => disp,
+        Err(e) => {
+            reject(&mut stream, Rejection::Malformed(e.to_string()));
+            return;
+        }
+    };
+
+    // give the client displays server-wide ids
+    for disp in client_disp.iter_mut() {
+        disp.owner = cid;
+        disp.local = disp.id;
+    }
+
+    let assigned = disp_ids
+        .write()
+        .unwrap()
+        .handles
+        .assign_all(&mut client_disp);
+
+    if let Err(e) = assigned {
+        reject(&mut stream, Rejection::Malformed(e.to_string()));
+        return;
+    }
+
+    /* other handshakes run at the same time; check and commit in one go */
+    let mut clients = clients.write().unwrap();
+    let mut displays = displays.write().unwrap();
+
+    if clients.contains_key(&cid) {
+        reject(&mut stream, Rejection::DuplicateCid(cid));
+        return;
+    }
+
+    if clients.len() >= config.max_clients {
+        reject(&mut stream, Rejection::ServerFull(config.max_clients));
+        return;
+    }
+
+    // update warpzones for new displays on a copy until the layout is known to be valid
+    let mut layout = displays.clone();
+
+    let new = match create_warpzones_hashmap(&mut layout, &mut client_disp) {
+        Ok(new) => new,
+        Err(e) => {
+            reject(&mut stream, layout_rejection(e));
+            return;
+        }
+    };
+
+    arrange(&mut layout, &config);
+
+    // what others left behind is not up to this client
+    let (fatal, warnings): (Vec<_>, Vec<_>) = layout_issues(&layout, config.gap_tolerance)
+        .into_iter()
+        .partition(|(x, owners)| x.is_fatal() && owners.contains(&cid));
+
+    for (issue, _) in warnings {
+        println!("[WRN] client {} layout: {}", ip, issue);
+    }
+
+    let fatal: Vec<LayoutIssue> = fatal.into_iter().map(|(x, _)| x).collect();
+
+    if !fatal.is_empty() {
+        let overlap = fatal.iter().any(|x| matches!(x, LayoutIssue::Overlap(..)));
+        let reasons: Vec<String> = fatal.iter().map(|x| x.to_string()).collect();
+        let reasons = reasons.join("; ");
+
+        let reason = match overlap {
+            true => Rejection::Overlap(reasons),
+            false => Rejection::Isolated(reasons),
         };
 
-        // transmit ack
-        if let Err(e) = tcp_write(&mut stream, HandshakeStatus::HandshakeOk as i32) {
+        reject(&mut stream, reason);
+        return;
+    }
+
+    // transmit ack
+    if let Err(e) = tcp_write(&mut stream, HandshakeStatus::HandshakeOk) {
+        eprintln!("[ERR] client {} handshake failed: {}", ip, e);
+        return;
+    };
+
+    // tell the client the current lock state
+    let lock_state = Message {
+        disp: 0,
+        action: Action::Lock(*lock.read().unwrap()),
+        x: 0,
+        y: 0,
+    };
+
+    if capabilities.contains(&Capability::Lock) {
+        if let Err(e) = tcp_write(&mut stream, lock_state) {
             eprintln!("[ERR] client {} handshake failed: {}", ip, e);
-            continue;
+            return;
         };
+    }
 
-        // set stream in nonblocking mode
-        stream
-            .set_nonblocking(true)
-            .expect("[ERR] set stream nonblocking failed");
+    // clients send requests of their own on the same connection
+    let reader = stream.try_clone();
 
-        // add accepted client and display list
-        let client = Client {
-            tcp: stream,
-            cid,
-            displays: Vec::new(), // not using at server
-        };
+    // add accepted client and display list
+    let client = Client {
+        tcp: stream,
+        cid,
+        displays: Vec::new(), // not using at server
+        capabilities,
+        pairing: None,
+    };
 
-        clients.write().unwrap().insert(cid, client);
-        disp_ids.write().unwrap().client.extend(new);
+    *displays = layout;
+    clients.insert(cid, client);
+    disp_ids.write().unwrap().client.extend(new);
 
-        println!("[INF] client {} connected!", ip);
-    }
-}
+    // the new displays change the ways out of the others as well
+    send_exits(&displays, &tx);
 
-fn transceive(
-    current: Arc<RwLock<Cid>>,
-    clients: Arc<RwLock<HashMap<Cid, Client>>>,
-    tx: Sender<Message>,
-    rx: Receiver<Message>,
-) {
-    let mut buffer = vec![0u8; 128];
+    drop(displays);
+    drop(clients);
 
-    loop {
-        let mut clients = clients.write().unwrap();
-        let cid = current.read().unwrap();
+    // the client is known from now on
+    if known.is_none() {
+        if let Err(e) = add_authorized_client(file, cid, &key) {
+            eprintln!("[ERR] failed to save client {}: {}", cid, e);
+        }
 
-        if *cid == 0 {
-            continue;
+        authorized.write().unwrap().insert(cid, key);
+
+        println!("[INF] client {} at {} paired", cid, ip);
+    }
+
+    match reader {
+        Ok(reader) => {
+            let server = owner.clone();
+
+            thread::spawn(move || listen(reader, server, cid, tx, cursor));
         }
+        Err(e) => eprintln!("[ERR] client {} listener failed: {}", ip, e),
+    }
 
-        let cur = clients.get_mut(&cid).unwrap();
+    println!("[INF] client {} connected!", ip);
+}
 
-        // check if there is something to transmit
-        match rx.try_recv() {
-            Ok(msg) => {
-                println!("[DBG] msg: {:?}", msg);
+/* the operator at the server accepts a new client or not; no answer is a no */
+fn confirm_pairing(cid: Cid, ip: SocketAddr, key: &[u8]) -> bool {
+    println!("[INF] client {} at {} wants to pair", cid, ip);
+    println!("[INF] key fingerprint: {}", fingerprint(key));
+    println!("[INF] compare it with the fingerprint the client shows");
 
-                if let Err(e) = tcp_write(&mut cur.tcp, msg) {
-                    eprintln!("[ERR] msg transfer failed: {}", e);
-                    continue;
-                }
+    loop {
+        print!("[CONFIRM] accept client {}? [y/n]: ", cid);
 
-                loop {
-                    // check if cursor warped back
-                    if let Err(e) = tcp_read(&mut cur.tcp, &mut buffer) {
-                        if e.kind() == WouldBlock {
-                            continue;
-                        }
+        if stdout().flush().is_err() {
+            return false;
+        }
 
-                        // TODO: proper error handling
-                        println!("[DBG] tcperr: {:?}", e);
+        let mut input = String::new();
 
-                        break;
-                    }
+        // no terminal attached
+        if !matches!(stdin().read_line(&mut input), Ok(n) if n > 0) {
+            eprintln!("[WRN] nobody to confirm client {}; declined", cid);
+            return false;
+        }
 
-                    println!("[DBG] cursor warped back!");
-                    break;
-                }
-            }
-            Err(TryRecvError::Empty) => {} // do nothing on empty recv
-            Err(e) => {
-                eprintln!("[ERR] message receive failed: {}", e);
-            }
+        match input.trim() {
+            "y" => return true,
+            "n" => return false,
+            _ => continue,
         }
     }
 }
 
-fn get_authorized_clients(file: PathBuf) -> Result<Vec<Cid>, Error> {
-    if !file.exists() {
-        fs::File::create(&file)?; // touch authorized_clients.json
+/* tell the client why its request is refused */
+fn reject(stream: &mut SecureStream, reason: Rejection) {
+    refuse(
+        stream,
+        &reason.to_string(),
+        HandshakeStatus::HandshakeRejected(reason),
+    );
+}
+
+/* what a client did wrong when its displays cannot join the layout */
+fn layout_rejection(e: Error) -> Rejection {
+    match e.kind() {
+        InvalidInput => Rejection::Overlap(e.to_string()),
+        _ => Rejection::Malformed(e.to_string()),
     }
+}
 
-    let json = fs::read_to_string(&file)?;
+fn refuse(stream: &mut SecureStream, reason: &str, status: HandshakeStatus) {
+    let ip = match stream.peer_addr() {
+        Ok(ip) => ip,
+        Err(e) => {
+            eprintln!(
+                "[ERR] client connection lost before refusing it ({}): {}",
+                reason, e
+            );
+            return;
+        }
+    };
 
-    if json.len() == 0 {
-        return Err(Error::new(
-            NotFound,
-            format!(
-                "client config is empty: {}",
-                file.as_os_str().to_str().unwrap()
-            ),
-        ));
+    eprintln!("[ERR] invalid request from client {} : {}", ip, reason);
+
+    if let Err(e) = tcp_write(stream, status) {
+        eprintln!("[ERR] client {} handshake failed: {}", ip, e);
     }
+}
 
-    let clients: Vec<AuthorizedClient> = serde_json::from_str(&json)?;
-    let clients_cid = clients.iter().map(|x| x.cid).collect();
+/* requests from a connected client until it disconnects */
+fn listen(
+    mut stream: SecureStream,
+    server: Server,
+    cid: Cid,
+    tx: Sender<Message>,
+    cursor: SharedCursor,
+) {
+    let ip = match stream.peer_addr() {
+        Ok(ip) => ip,
+        Err(e) => {
+            eprintln!("[ERR] client {} connection lost: {}", cid, e);
+            return disconnect(&server, cid, &tx);
+        }
+    };
+    let mut buffer = Vec::new();
+
+    while tcp_read(&mut stream, &mut buffer).is_ok() {
+        let msg: Message = match deserialize(&buffer) {
+            Ok(msg) => msg,
+            Err(e) => {
+                eprintln!("[ERR] invalid request from client {} : {}", ip, e);
+                continue;
+            }
+        };
+
+        match msg.action {
+            Action::Lock(locked) => set_lock(&server.lock, locked, &tx),
+            Action::Leave(direction) => leave(&server, cid, &msg, direction, &cursor, &tx),
+            action => eprintln!("[WRN] unexpected request from client {} : {:?}", ip, action),
+        }
+    }
+
+    println!("[INF] client {} disconnected", ip);
+
+    disconnect(&server, cid, &tx);
+}
+
+/* the cursor was moved off a client display on the client itself; follow it there */
+fn leave(
+    server: &Server,
+    cid: Cid,
+    msg: &Message,
+    direction: ZoneDirection,
+    cursor: &SharedCursor,
+    tx: &Sender<Message>,
+) {
+    if *server.lock.read().unwrap() {
+        return;
+    }
+
+    let mut focus = server.focus.write().unwrap();
+    let mut current = server.current.write().unwrap();
+    let mut pointer = server.pointer.write().unwrap();
+
+    let displays = server.displays.read().unwrap();
+    let crossing = server.config.crossing();
+
+    let (to, (x, y)) = match leave_target(&displays, *focus, cid, msg, direction, &crossing) {
+        Some(target) => target,
+        None => return,
+    };
+
+    release_held(&server.held, *focus, *current, to.owner, tx);
+
+    *focus = to.id;
+    *current = to.owner;
+    *pointer = (x, y);
+
+    let (x, y) = to.global_to_system(x, y);
+
+    if to.owner == SERVER_CID {
+        // back on the server; hand the cursor back to local apps
+        let mut cursor = cursor.lock().unwrap();
+
+        if let Err(e) = cursor.confine(false).and_then(|_| cursor.warp(x, y)) {
+            eprintln!("[ERR] cursor release failed: {}", e);
+        }
+
+        return;
+    }
+
+    let msg = Message {
+        disp: to.id,
+        action: Action::Warp,
+        x,
+        y,
+    };
+
+    if let Err(e) = tx.send(msg) {
+        eprintln!("[ERR] mpsc tx failed: {}", e);
+    }
+}
+
+/* where a cursor leaving display `msg.disp` of client `cid` lands; None unless that display has focus */
+fn leave_target<'a>(
+    displays: &'a HashMap<Did, Display>,
+    focus: Did,
+    cid: Cid,
+    msg: &Message,
+    direction: ZoneDirection,
+    crossing: &Crossing,
+) -> Option<(&'a Display, (i32, i32))> {
+    let from = displays
+        .values()
+        .find(|x| x.owner == cid && x.local == msg.disp)?;
+
+    // the virtual cursor has moved on already
+    if from.id != focus {
+        return None;
+    }
+
+    let (x, y) = from.system_to_global(msg.x, msg.y);
+
+    let motion = match direction {
+        ZoneDirection::HorizontalLeft => (-1, 0),
+        ZoneDirection::HorizontalRight => (1, 0),
+        ZoneDirection::VerticalUp => (0, -1),
+        ZoneDirection::VerticalDown => (0, 1),
+    };
+
+    let wz = from.find_warpzone(x, y, motion, crossing)?;
+    let to = displays.get(&wz.to)?;
+
+    Some((to, from.cross(wz, to, x, y, crossing.mapping)))
+}
+
+/* forget a client and its displays; the cid may connect again */
+fn disconnect(server: &Server, cid: Cid, tx: &Sender<Message>) {
+    // nobody left to release them to
+    let focused = *server.current.read().unwrap() == cid;
+
+    if focused {
+        server.held.lock().unwrap().release();
+    }
+
+    server.clients.write().unwrap().remove(&cid);
+
+    let mut displays = server.displays.write().unwrap();
+    displays.retain(|_, x| x.owner != cid);
+
+    let ids: HashSet<Did> = displays.keys().copied().collect();
+
+    for disp in displays.values_mut() {
+        disp.warpzones.retain(|wz| ids.contains(&wz.to));
+    }
+
+    arrange(&mut displays, &server.config);
+    send_exits(&displays, tx);
+
+    // displays of other clients may hang off no more than the one that left
+    for issue in validate_layout(&displays, server.config.gap_tolerance) {
+        if issue.is_fatal() {
+            println!("[WRN] client {} left behind: {}", cid, issue);
+        }
+    }
+
+    server
+        .disp_ids
+        .write()
+        .unwrap()
+        .client
+        .retain(|x| ids.contains(x));
+}
+
+fn transceive(server: Server, rx: Receiver<Message>) {
+    let Server {
+        clients, disp_ids, ..
+    } = server;
+
+    for mut msg in rx {
+        let mut clients = clients.write().unwrap();
+
+        if let Action::Lock(_) = msg.action {
+            for client in clients.values_mut() {
+                if !client.capabilities.contains(&Capability::Lock) {
+                    continue;
+                }
+
+                if let Err(e) = tcp_write(&mut client.tcp, &msg) {
+                    eprintln!("[ERR] msg transfer failed: {}", e);
+                }
+            }
+
+            continue;
+        }
+        let disp = msg.disp;
+        let owner = addressee(&disp_ids.read().unwrap().handles, &mut msg);
+
+        match owner.and_then(|x| clients.get_mut(&x)) {
+            Some(client) if !client.capabilities.contains(&msg.action.capability()) => {}
+            Some(client) => {
+                if let Err(e) = tcp_write(&mut client.tcp, msg) {
+                    eprintln!("[ERR] msg transfer failed: {}", e);
+                }
+            }
+            None => eprintln!("[ERR] no client owns display {}", disp),
+        }
+    }
+}
+
+/* owner of the display a message is for; clients know their displays by their own ids */
+fn addressee(handles: &DisplayHandles, msg: &mut Message) -> Option<Cid> {
+    let (owner, local) = handles.local(msg.disp)?;
+    msg.disp = local;
+
+    Some(owner)
+}
+
+fn get_server_config(file: PathBuf) -> Result<ServerConfig, Error> {
+    if !file.exists() {
+        return Ok(ServerConfig::default());
+    }
+
+    let json = fs::read_to_string(&file)?;
+
+    serde_json::from_str(&json).map_err(|e| {
+        Error::new(
+            InvalidData,
+            format!(
+                "invalid server config {}: {}",
+                file.as_os_str().to_str().unwrap(),
+                e
+            ),
+        )
+    })
+}
+
+/* a pairing code is good for a few minutes and a few tries */
+fn check_pairing(pairing: &RwLock<Option<Pairing>>, code: &str) -> Result<(), Rejection> {
+    let mut pairing = pairing.write().unwrap();
+
+    let state = match pairing.as_mut() {
+        Some(state) if state.expires > Instant::now() => state,
+        _ => return Err(Rejection::PairingClosed),
+    };
+
+    // one pairing at a time; the code is used up once presented
+    if state.code == code {
+        *pairing = None;
+        return Ok(());
+    }
+
+    state.attempts += 1;
+
+    if state.attempts >= PAIRING_ATTEMPTS {
+        eprintln!("[WRN] too many wrong pairing codes; pairing closed");
+        *pairing = None;
+    }
+
+    Err(Rejection::WrongCode)
+}
+
+fn add_authorized_client(file: &PathBuf, cid: Cid, key: &[u8]) -> Result<(), Error> {
+    // a list we cannot read must not be replaced by a list of one
+    let json = match fs::read_to_string(file) {
+        Ok(json) => json,
+        Err(e) if e.kind() == NotFound => String::new(),
+        Err(e) => return Err(e),
+    };
+
+    let mut clients: Vec<AuthorizedClient> = match json.trim().is_empty() {
+        true => Vec::new(),
+        false => serde_json::from_str(&json)?,
+    };
+
+    clients.retain(|x| x.cid != cid);
+    clients.push(AuthorizedClient {
+        cid,
+        key: Some(hex::encode(key)),
+    });
+
+    write_atomic(file, serde_json::to_string_pretty(&clients)?.as_bytes())
+}
+
+/* public keys of the authorized clients by cid */
+fn get_authorized_clients(file: PathBuf) -> Result<HashMap<Cid, Vec<u8>>, Error> {
+    if !file.exists() {
+        fs::File::create(&file)?; // touch authorized_clients.json
+    }
+
+    let json = fs::read_to_string(&file)?;
+
+    // nobody paired yet
+    if json.trim().is_empty() {
+        println!("[INF] no authorized clients yet; start the server with --pair to add one");
+        return Ok(HashMap::new());
+    }
+
+    let clients: Vec<AuthorizedClient> = serde_json::from_str(&json)?;
+    let mut keys = HashMap::new();
+
+    for client in clients {
+        match client.key.as_deref().map(hex::decode) {
+            Some(Ok(key)) => {
+                keys.insert(client.cid, key);
+            }
+            Some(Err(e)) => eprintln!("[WRN] client {} has an invalid key: {}", client.cid, e),
+            None => eprintln!(
+                "[WRN] client {} has no key and cannot connect; copy it from the client output",
+                client.cid
+            ),
+        }
+    }
+
+    Ok(keys)
+}
+
+#[cfg(test)]
+mod tests {
+    use ring::rand::SystemRandom;
+    use ring::signature::{Ed25519KeyPair, KeyPair};
+
+    use super::*;
+
+    const CID: Cid = 7;
+
+    // server display at the origin and a client display right of it, as the server sees them
+    fn server_layout() -> HashMap<Did, Display> {
+        let mut server = VirtualDisplays::parse("1920x1080")
+            .unwrap()
+            .displays(SERVER_CID)
+            .unwrap();
+        let mut client = client_layout();
+
+        client[0].id = 2;
+        client[0].warpzones.clear();
+
+        let mut layout = HashMap::new();
+        layout.insert(server[0].id, server.remove(0));
+        create_warpzones_hashmap(&mut layout, &mut client).unwrap();
+
+        layout
+    }
+
+    // the same client display as the client sees it; its own id and OS coordinates from 0
+    fn client_layout() -> Vec<Display> {
+        let mut ours = VirtualDisplays::parse("1920x1080")
+            .unwrap()
+            .displays(CID)
+            .unwrap();
+        let mut others = VirtualDisplays::parse("1920x1080")
+            .unwrap()
+            .displays(SERVER_CID)
+            .unwrap();
+
+        ours[0].x = 1920;
+        create_warpzones(&mut ours, &mut others, false).unwrap();
+
+        ours
+    }
+
+    // the layout above with the focus on the server display; nothing touches the disk or the network
+    fn test_server(config: ServerConfig) -> Server {
+        let cert = rcgen::generate_simple_self_signed(vec!["transistor".to_string()]).unwrap();
+        let tls = rustls::ServerConfig::builder()
+            .with_safe_defaults()
+            .with_no_client_auth()
+            .with_single_cert(
+                vec![rustls::Certificate(cert.serialize_der().unwrap())],
+                rustls::PrivateKey(cert.serialize_private_key_der()),
+            )
+            .unwrap();
+
+        Server {
+            clients: Arc::new(RwLock::new(HashMap::new())),
+            displays: Arc::new(RwLock::new(server_layout())),
+            disp_ids: Arc::new(RwLock::new(AssignedDisplays {
+                system: vec![1],
+                client: vec![2],
+                handles: {
+                    let mut handles = DisplayHandles::default();
+                    handles.assign(SERVER_CID, 1);
+                    handles.assign(CID, 1);
+                    handles
+                },
+            })),
+            focus: Arc::new(RwLock::new(1)),
+            current: Arc::new(RwLock::new(SERVER_CID)),
+            pointer: Arc::new(RwLock::new((0, 0))),
+            lock: Arc::new(RwLock::new(false)),
+            held: Arc::new(Mutex::new(HeldInput::default())),
+            tls: Arc::new(tls),
+            pairing: Arc::new(RwLock::new(None)),
+            config,
+        }
+    }
+
+    // the server end of a handshake runs on a thread of its own; the client end is up to the test
+    fn accept(server: &Server, authorized: HashMap<Cid, Vec<u8>>) -> SecureStream {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = server.clone();
+
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let (tx, _) = channel();
+            let cursor: SharedCursor = Arc::new(Mutex::new(MockSource::new(0, 0).cursor()));
+            let file = std::env::temp_dir().join("transistor-unused-authorized-clients.json");
+
+            handshake(server, stream, &file, &RwLock::new(authorized), tx, cursor);
+        });
+
+        SecureStream::connect_unpinned(TcpStream::connect(addr).unwrap()).unwrap()
+    }
+
+    fn client_key() -> Ed25519KeyPair {
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+        Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap()
+    }
+
+    // hello, challenge and response as client `cid` holding `key`; the status the server ends with
+    fn authenticate(stream: &mut SecureStream, cid: Cid, key: &Ed25519KeyPair) -> HandshakeStatus {
+        let hello = Hello {
+            version: PROTOCOL_VERSION,
+            cid,
+            capabilities: CAPABILITIES.to_vec(),
+            nonce: rand::random(),
+            pairing: None,
+        };
+        let nonce = hello.nonce;
+        let mut buffer = Vec::new();
+
+        tcp_write(stream, hello).unwrap();
+        tcp_read(stream, &mut buffer).unwrap();
+
+        // every hello of this version is challenged, whoever it claims to be
+        let challenge = match deserialize(&buffer).unwrap() {
+            HandshakeStatus::HandshakeChallenge(challenge) => challenge,
+            status => panic!("hello answered without a challenge: {:?}", status),
+        };
+        let binding = stream.binding().unwrap();
+        let payload = challenge_payload(cid, &nonce, &challenge.nonce, &binding);
+
+        let response = Response {
+            signature: key.sign(&payload).as_ref().to_vec(),
+        };
+
+        tcp_write(stream, response).unwrap();
+        tcp_read(stream, &mut buffer).unwrap();
+
+        deserialize(&buffer).unwrap()
+    }
+
+    // a 1920x1080 display of client `cid` at (x, 0), sent after the Welcome; the status the server ends with
+    fn attach(stream: &mut SecureStream, cid: Cid, x: i32) -> HandshakeStatus {
+        let mut displays = VirtualDisplays::parse("1920x1080")
+            .unwrap()
+            .displays(cid)
+            .unwrap();
+        displays[0].x = x;
+
+        let mut buffer = Vec::new();
+
+        tcp_write(stream, displays).unwrap();
+        tcp_read(stream, &mut buffer).unwrap();
+
+        deserialize(&buffer).unwrap()
+    }
+
+    // a whole handshake of a new authorized client `cid` with a display at (x, 0)
+    fn join(server: &Server, cid: Cid, x: i32) -> HandshakeStatus {
+        let key = client_key();
+        let authorized = HashMap::from([(cid, key.public_key().as_ref().to_vec())]);
+
+        let mut stream = accept(server, authorized);
+        let mut buffer = Vec::new();
+
+        match authenticate(&mut stream, cid, &key) {
+            HandshakeStatus::HandshakeOk => {}
+            status => return status,
+        }
+
+        tcp_read(&mut stream, &mut buffer).unwrap();
+        let _: Welcome = deserialize(&buffer).unwrap();
+
+        attach(&mut stream, cid, x)
+    }
+
+    // what the server sends out for the scripted input
+    fn run(server: &Server, mut source: MockSource) -> Vec<Message> {
+        let (tx, rx) = channel();
+
+        server.run(&mut source, tx).unwrap();
+
+        rx.into_iter().collect()
+    }
 
-    Ok(clients_cid)
+    // pushes the cursor through the right edge of the server display onto the client display
+    fn to_client(source: MockSource) -> MockSource {
+        source
+            .then(InputEvent::Motion(8, 0))
+            .then(InputEvent::Position(1919, 540))
+    }
+
+    fn key(key: Key) -> KeyInput {
+        KeyInput {
+            key,
+            scan: 0,
+            modifiers: Modifiers::default(),
+        }
+    }
+
+    // horizontal travel of the cursor on the client after the entry warp
+    fn travel(server: &Server, source: MockSource) -> i32 {
+        let warps: Vec<Message> = run(server, source)
+            .into_iter()
+            .filter(|x| matches!(x.action, Action::Warp))
+            .collect();
+
+        warps.last().unwrap().x - warps[0].x
+    }
+
+    #[test]
+    fn motion_covers_the_same_distance_on_every_display() {
+        // same resolution on half the width; twice the pixels per millimeter
+        let server = test_server(ServerConfig::default());
+        server
+            .displays
+            .write()
+            .unwrap()
+            .get_mut(&2)
+            .unwrap()
+            .width_mm /= 2;
+
+        let source = to_client(MockSource::new(960, 540)).then(InputEvent::Motion(10, 0));
+        assert_eq!(travel(&server, source), 20);
+
+        // twice the width; fractions of a pixel add up
+        let server = test_server(ServerConfig::default());
+        server
+            .displays
+            .write()
+            .unwrap()
+            .get_mut(&2)
+            .unwrap()
+            .width_mm *= 2;
+
+        let source = to_client(MockSource::new(960, 540)).motion(4, 0, 4);
+        assert_eq!(travel(&server, source), 2);
+    }
+
+    #[test]
+    fn link_between_server_displays_moves_the_cursor() {
+        // two server displays far apart; nothing moves the cursor from one to the other but us
+        let mut layout: HashMap<Did, Display> =
+            VirtualDisplays::parse("1920x1080,1920x1080+4000+0")
+                .unwrap()
+                .displays(SERVER_CID)
+                .unwrap()
+                .into_iter()
+                .map(|x| (x.id, x))
+                .collect();
+
+        let name = |name: &str| DisplayRef {
+            owner: SERVER_CID,
+            name: name.into(),
+        };
+
+        apply_links(
+            &mut layout,
+            &[DisplayLink {
+                from: name("virtual-0"),
+                edge: ZoneDirection::HorizontalRight,
+                to: name("virtual-1"),
+                range: None,
+                to_range: None,
+            }],
+        );
+
+        let server = test_server(ServerConfig::default());
+        *server.displays.write().unwrap() = layout;
+        server.disp_ids.write().unwrap().system = vec![1, 2];
+        server.disp_ids.write().unwrap().client.clear();
+
+        let mut source = to_client(MockSource::new(960, 540));
+        let (tx, rx) = channel();
+        server.run(&mut source, tx).unwrap();
+
+        assert_eq!(rx.try_iter().count(), 0);
+        assert_eq!(source.position().unwrap(), (4000 + MARGIN + 1, 540));
+        assert_eq!(*server.focus.read().unwrap(), 2);
+    }
+
+    #[test]
+    fn focus_follows_the_os_between_server_displays() {
+        let layout: HashMap<Did, Display> = VirtualDisplays::parse("1920x1080,1920x1080")
+            .unwrap()
+            .displays(SERVER_CID)
+            .unwrap()
+            .into_iter()
+            .map(|x| (x.id, x))
+            .collect();
+
+        assert_eq!(display_at(&layout, SERVER_CID, 2500, 540).unwrap().id, 2);
+        assert!(display_at(&layout, SERVER_CID, -1, 540).is_none());
+        assert!(display_at(&layout, CID, 100, 540).is_none());
+
+        let server = test_server(ServerConfig::default());
+        *server.displays.write().unwrap() = layout;
+        server.disp_ids.write().unwrap().system = vec![1, 2];
+        server.disp_ids.write().unwrap().client.clear();
+
+        // moved over by the OS, not through a warpzone
+        let source = MockSource::new(960, 540).then(InputEvent::Position(2500, 540));
+        run(&server, source);
+
+        assert_eq!(*server.focus.read().unwrap(), 2);
+    }
+
+    #[test]
+    fn lock_chord_stays_on_the_server() {
+        let server = test_server(ServerConfig {
+            lock_key: vec![Key::LControl, Key::ScrollLock],
+            ..ServerConfig::default()
+        });
+
+        let source = to_client(MockSource::new(960, 540))
+            .then(InputEvent::Key(key(Key::LControl), true))
+            .then(InputEvent::Key(key(Key::ScrollLock), true))
+            .then(InputEvent::Key(key(Key::ScrollLock), true)) // repeat
+            .then(InputEvent::Key(key(Key::ScrollLock), false))
+            .then(InputEvent::Key(key(Key::LControl), false))
+            .then(InputEvent::Key(key(Key::ScrollLock), true))
+            .then(InputEvent::Key(key(Key::ScrollLock), false));
+
+        let actions: Vec<Action> = run(&server, source).into_iter().map(|x| x.action).collect();
+
+        assert!(matches!(
+            actions[..],
+            [
+                Action::Warp,
+                Action::KeyDown(KeyInput {
+                    key: Key::LControl,
+                    ..
+                }),
+                Action::Lock(true),
+                Action::KeyUp(KeyInput {
+                    key: Key::LControl,
+                    ..
+                }),
+                Action::KeyDown(KeyInput {
+                    key: Key::ScrollLock,
+                    ..
+                }),
+                Action::KeyUp(KeyInput {
+                    key: Key::ScrollLock,
+                    ..
+                }),
+            ]
+        ));
+    }
+
+    fn scratch(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("transistor-{}-{}", name, std::process::id()));
+
+        fs::create_dir_all(&dir).unwrap();
+        dir.join("authorized_clients.json")
+    }
+
+    #[test]
+    fn authorized_client_is_added_to_the_others() {
+        let file = scratch("add");
+        fs::write(
+            &file,
+            r#"[{ "cid": 1, "key": "0102" }, { "cid": 2, "key": "0304" }]"#,
+        )
+        .unwrap();
+
+        add_authorized_client(&file, 2, &[5, 6]).unwrap();
+        add_authorized_client(&file, 3, &[7, 8]).unwrap();
+
+        let keys = get_authorized_clients(file.clone()).unwrap();
+
+        assert_eq!(keys.len(), 3);
+        assert_eq!(keys[&1], vec![1, 2]);
+        assert_eq!(keys[&2], vec![5, 6]);
+        assert_eq!(keys[&3], vec![7, 8]);
+        assert!(!file.with_extension("json.tmp").exists());
+    }
+
+    #[test]
+    fn authorized_client_starts_a_new_list() {
+        let file = scratch("new");
+        let _ = fs::remove_file(&file);
+
+        add_authorized_client(&file, 1, &[1, 2]).unwrap();
+
+        fs::write(&file, "  \n").unwrap();
+        add_authorized_client(&file, 2, &[3, 4]).unwrap();
+
+        let keys = get_authorized_clients(file).unwrap();
+
+        assert_eq!(keys.len(), 1);
+        assert_eq!(keys[&2], vec![3, 4]);
+    }
+
+    #[test]
+    fn unreadable_authorized_clients_stay_untouched() {
+        let file = scratch("broken");
+        fs::write(&file, "[{ \"cid\": 1,").unwrap();
+
+        assert!(add_authorized_client(&file, 2, &[3, 4]).is_err());
+        assert_eq!(fs::read_to_string(&file).unwrap(), "[{ \"cid\": 1,");
+
+        // a directory where the file should be
+        let dir = scratch("dir");
+        let _ = fs::remove_file(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        assert!(add_authorized_client(&dir, 2, &[3, 4]).is_err());
+    }
+
+    fn pairing(code: &str, expires: Duration) -> RwLock<Option<Pairing>> {
+        RwLock::new(Some(Pairing {
+            code: code.to_string(),
+            expires: Instant::now() + expires,
+            attempts: 0,
+        }))
+    }
+
+    #[test]
+    fn pairing_code_is_good_once() {
+        let state = pairing("123456", PAIRING_TIMEOUT);
+
+        assert_eq!(check_pairing(&state, "123456"), Ok(()));
+        assert_eq!(
+            check_pairing(&state, "123456"),
+            Err(Rejection::PairingClosed)
+        );
+    }
+
+    #[test]
+    fn pairing_closes_after_wrong_codes() {
+        let state = pairing("123456", PAIRING_TIMEOUT);
+
+        for _ in 0..PAIRING_ATTEMPTS {
+            assert_eq!(check_pairing(&state, "654321"), Err(Rejection::WrongCode));
+        }
+
+        assert_eq!(
+            check_pairing(&state, "123456"),
+            Err(Rejection::PairingClosed)
+        );
+    }
+
+    #[test]
+    fn pairing_expires() {
+        let state = pairing("123456", Duration::ZERO);
+
+        assert_eq!(
+            check_pairing(&state, "123456"),
+            Err(Rejection::PairingClosed)
+        );
+        assert_eq!(
+            check_pairing(&RwLock::new(None), "123456"),
+            Err(Rejection::PairingClosed)
+        );
+    }
+
+    #[test]
+    fn client_exit_returns_to_the_server() {
+        let layout = server_layout();
+        let sink = RecordingSink {
+            events: Vec::new(),
+            position: (0, 540), // left edge of the client display
+        };
+
+        let msg = find_exit(&sink, &client_layout()).unwrap().unwrap();
+
+        let direction = match msg.action {
+            Action::Leave(direction) => direction,
+            action => panic!("expected Leave, got {:?}", action),
+        };
+
+        assert_eq!(direction, ZoneDirection::HorizontalLeft);
+        assert_eq!((msg.disp, msg.x, msg.y), (1, 0, 540));
+
+        let crossing = Crossing::default();
+        let (to, (x, y)) = leave_target(&layout, 2, CID, &msg, direction, &crossing).unwrap();
+
+        assert_eq!(to.owner, SERVER_CID);
+        assert!(to.contains(x, y));
+        assert_eq!(y, 540);
+    }
+
+    #[test]
+    fn client_exit_needs_an_edge() {
+        let sink = RecordingSink {
+            events: Vec::new(),
+            position: (960, 540),
+        };
+
+        assert!(find_exit(&sink, &client_layout()).unwrap().is_none());
+
+        // nothing right of the client display
+        let sink = RecordingSink {
+            events: Vec::new(),
+            position: (1919, 540),
+        };
+
+        assert!(find_exit(&sink, &client_layout()).unwrap().is_none());
+    }
+
+    #[test]
+    fn client_exits_follow_the_layout_of_the_server() {
+        let mut layout = server_layout();
+        apply_wrap(&mut layout, WrapMode::Horizontal);
+
+        let (tx, rx) = channel();
+        send_exits(&layout, &tx);
+        drop(tx);
+
+        let server = test_server(ServerConfig::default());
+        let handles = &server.disp_ids.read().unwrap().handles;
+
+        // the client knows nothing of the wrap but what it is told
+        let mut ours = client_layout();
+        ours[0].warpzones.clear();
+
+        for mut msg in rx {
+            assert_eq!(addressee(handles, &mut msg), Some(CID));
+
+            match msg.action {
+                Action::Exits(zones) => set_exits(&mut ours, msg.disp, zones),
+                action => panic!("expected Exits, got {:?}", action),
+            }
+        }
+
+        // the right edge touches nothing but wraps around to the server
+        let sink = RecordingSink {
+            events: Vec::new(),
+            position: (1919, 540),
+        };
+        let msg = find_exit(&sink, &ours).unwrap().unwrap();
+
+        let direction = match msg.action {
+            Action::Leave(direction) => direction,
+            action => panic!("expected Leave, got {:?}", action),
+        };
+        assert_eq!(direction, ZoneDirection::HorizontalRight);
+
+        let crossing = Crossing::default();
+        let (to, _) = leave_target(&layout, 2, CID, &msg, direction, &crossing).unwrap();
+        assert_eq!(to.owner, SERVER_CID);
+    }
+
+    #[test]
+    fn stale_exit_is_ignored() {
+        let layout = server_layout();
+        let msg = Message {
+            disp: 1,
+            action: Action::Leave(ZoneDirection::HorizontalLeft),
+            x: 0,
+            y: 540,
+        };
+        let direction = ZoneDirection::HorizontalLeft;
+        let crossing = Crossing::default();
+
+        // the virtual cursor is back on the server already
+        assert!(leave_target(&layout, 1, CID, &msg, direction, &crossing).is_none());
+
+        // somebody else's display
+        assert!(leave_target(&layout, 2, CID + 1, &msg, direction, &crossing).is_none());
+    }
+
+    #[test]
+    fn dwell_ends_while_the_cursor_rests_at_the_edge() {
+        let server = test_server(ServerConfig {
+            guard: SwitchGuard {
+                dwell: 100,
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+        let (input, mut source) = MockSource::live(960, 540);
+
+        // one push each way, then nothing but waiting
+        thread::spawn(move || {
+            input.send(InputEvent::Motion(8, 0)).unwrap();
+            input.send(InputEvent::Position(1919, 540)).unwrap();
+            thread::sleep(Duration::from_millis(300));
+            input.send(InputEvent::Motion(-3000, 0)).unwrap();
+            thread::sleep(Duration::from_millis(300));
+        });
+
+        let (tx, rx) = channel();
+        server.run(&mut source, tx).unwrap();
+
+        // entry onto the client, then along to its left edge
+        let warps: Vec<Message> = rx
+            .into_iter()
+            .filter(|x| matches!(x.action, Action::Warp))
+            .collect();
+        assert_eq!(warps.len(), 2);
+        assert_eq!(warps[1].x, 0);
+
+        // and back on the server without another motion
+        assert_eq!(*server.current.read().unwrap(), SERVER_CID);
+        assert!(!source.is_confined());
+        assert!(source.position().unwrap().0 > 1900);
+    }
+
+    #[test]
+    fn layout_errors_are_refused_for_what_they_are() {
+        let mut layout = server_layout();
+        let mut client = client_layout();
+
+        // on top of the server display
+        client[0].id = 3;
+        client[0].x = 0;
+        client[0].warpzones.clear();
+
+        let e = create_warpzones_hashmap(&mut layout, &mut client).unwrap_err();
+        assert!(matches!(layout_rejection(e), Rejection::Overlap(..)));
+
+        let e = Error::new(InvalidData, "broken display");
+        assert!(matches!(layout_rejection(e), Rejection::Malformed(..)));
+    }
+
+    #[test]
+    fn silent_client_is_dropped_before_authentication() {
+        let server = test_server(ServerConfig::default());
+        let mut stream = accept(&server, HashMap::new());
+        let mut buffer = Vec::new();
+
+        // no hello; the server gives up on its own
+        assert!(tcp_read(&mut stream, &mut buffer).is_err());
+    }
+
+    #[test]
+    fn known_and_unknown_cids_are_answered_alike() {
+        let server = test_server(ServerConfig::default());
+        let known = CID + 1;
+        let unknown = CID + 2;
+        let authorized = HashMap::from([(known, client_key().public_key().as_ref().to_vec())]);
+
+        // somebody without either key tries both cids
+        let mut stream = accept(&server, authorized.clone());
+        let status = authenticate(&mut stream, known, &client_key());
+        assert!(matches!(
+            status,
+            HandshakeStatus::HandshakeRejected(Rejection::Unauthorized(x)) if x == known
+        ));
+
+        let mut stream = accept(&server, authorized);
+        let status = authenticate(&mut stream, unknown, &client_key());
+        assert!(matches!(
+            status,
+            HandshakeStatus::HandshakeRejected(Rejection::Unauthorized(x)) if x == unknown
+        ));
+    }
+
+    #[test]
+    fn client_may_take_its_time_placing_its_displays() {
+        let server = test_server(ServerConfig::default());
+        let key = client_key();
+        let cid = CID + 1;
+        let authorized = HashMap::from([(cid, key.public_key().as_ref().to_vec())]);
+
+        let mut stream = accept(&server, authorized);
+        let mut buffer = Vec::new();
+
+        let status = authenticate(&mut stream, cid, &key);
+        assert!(matches!(status, HandshakeStatus::HandshakeOk));
+
+        tcp_read(&mut stream, &mut buffer).unwrap();
+        let _: Welcome = deserialize(&buffer).unwrap();
+
+        // somebody answers prompt_display_position
+        thread::sleep(HANDSHAKE_TIMEOUT * 2);
+
+        let status = attach(&mut stream, cid, -1920);
+        assert!(matches!(status, HandshakeStatus::HandshakeOk));
+    }
+
+    #[test]
+    fn input_held_on_a_client_is_released_when_the_cursor_leaves() {
+        let server = test_server(ServerConfig::default());
+        let source = to_client(MockSource::new(960, 540))
+            .then(InputEvent::Key(key(Key::LControl), true))
+            .then(InputEvent::Button(Button::Left, true))
+            .then(InputEvent::Motion(-3000, 0));
+
+        // what the client makes of it
+        let mut sink = RecordingSink::default();
+
+        for msg in run(&server, source) {
+            assert_eq!(msg.disp, 2);
+            inject(&mut sink, msg).unwrap();
+        }
+
+        let presses: Vec<SinkEvent> = sink
+            .events
+            .into_iter()
+            .filter(|x| matches!(x, SinkEvent::Key(..) | SinkEvent::Button(..)))
+            .collect();
+
+        assert_eq!(
+            presses,
+            vec![
+                SinkEvent::Key(Key::LControl, true),
+                SinkEvent::Button(Button::Left, true),
+                SinkEvent::Key(Key::LControl, false),
+                SinkEvent::Button(Button::Left, false),
+            ]
+        );
+        assert_eq!(*server.current.read().unwrap(), SERVER_CID);
+    }
+
+    #[test]
+    fn input_held_on_a_client_is_dropped_with_it() {
+        let server = test_server(ServerConfig::default());
+        *server.current.write().unwrap() = CID;
+        server
+            .held
+            .lock()
+            .unwrap()
+            .update(&Action::ButtonDown(Button::Left));
+
+        disconnect(&server, CID, &channel().0);
+
+        assert!(server.held.lock().unwrap().release().is_empty());
+    }
+
+    #[test]
+    fn displays_left_behind_by_a_client_reject_nobody_else() {
+        let server = test_server(ServerConfig::default());
+
+        // another client right of CID, reachable only through it
+        {
+            let mut displays = server.displays.write().unwrap();
+            let mut other = VirtualDisplays::parse("1920x1080")
+                .unwrap()
+                .displays(CID + 2)
+                .unwrap();
+
+            other[0].x = 3840;
+            server
+                .disp_ids
+                .write()
+                .unwrap()
+                .handles
+                .assign_all(&mut other)
+                .unwrap();
+            create_warpzones_hashmap(&mut displays, &mut other).unwrap();
+        }
+
+        let (tx, rx) = channel();
+        disconnect(&server, CID, &tx);
+        drop(tx);
+
+        // the one left behind learns it has no way out any more
+        let exits: Vec<Message> = rx.into_iter().collect();
+        assert_eq!(exits.len(), 1);
+        assert!(matches!(&exits[0].action, Action::Exits(zones) if zones.is_empty()));
+
+        let issues = validate_layout(&server.displays.read().unwrap(), GAP_TOLERANCE);
+        assert_eq!(issues, vec![LayoutIssue::Unreachable("virtual-0".into())]);
+
+        // fine on its own
+        let status = join(&server, CID + 1, -1920);
+        assert!(matches!(status, HandshakeStatus::HandshakeOk));
+
+        // but a client of its own still has to be reachable
+        let status = join(&server, CID + 3, -5760);
+        assert!(matches!(
+            status,
+            HandshakeStatus::HandshakeRejected(Rejection::Isolated(..))
+        ));
+    }
+
+    #[test]
+    fn messages_reach_clients_under_their_own_display_ids() {
+        let server = test_server(ServerConfig::default());
+        let mut ids = server.disp_ids.write().unwrap();
+
+        // the second client numbers its display 1 as well
+        let other = ids.handles.assign(CID + 1, 1);
+        assert_ne!(other, 2);
+
+        for (disp, owner) in [(2, CID), (other, CID + 1)] {
+            let mut msg = Message {
+                disp,
+                action: Action::Warp,
+                x: 0,
+                y: 0,
+            };
+
+            assert_eq!(addressee(&ids.handles, &mut msg), Some(owner));
+            assert_eq!(msg.disp, 1);
+        }
+
+        let mut msg = Message {
+            disp: 99,
+            action: Action::Warp,
+            x: 0,
+            y: 0,
+        };
+        assert_eq!(addressee(&ids.handles, &mut msg), None);
+    }
+
+    #[test]
+    fn virtual_cursor_moves_between_displays_of_one_client() {
+        let mut layout: HashMap<Did, Display> = VirtualDisplays::parse("1920x1080")
+            .unwrap()
+            .displays(SERVER_CID)
+            .unwrap()
+            .into_iter()
+            .map(|x| (x.id, x))
+            .collect();
+        let mut client = VirtualDisplays::parse("1920x1080+1920+0,1920x1080+3840+0")
+            .unwrap()
+            .displays(CID)
+            .unwrap();
+
+        client[0].id = 2;
+        client[1].id = 3;
+        create_warpzones_hashmap(&mut layout, &mut client).unwrap();
+
+        let mut guard = EdgeGuard::new(
+            SwitchGuard::default(),
+            Vec::new(),
+            Arc::new(RwLock::new(false)),
+        );
+        let crossing = Crossing::default();
+
+        let (to, (x, y)) = track(&layout, 2, (3830, 540), 20, 0, crossing, &mut guard).unwrap();
+        assert_eq!(to.id, 3);
+        assert!(
+            (3840..3840 + MARGIN * 2 + 1).contains(&x),
+            "entered at x = {}",
+            x
+        );
+        assert_eq!(y, 540);
+
+        // and back
+        let (to, (x, _)) = track(&layout, 3, (3845, 540), -20, 0, crossing, &mut guard).unwrap();
+        assert_eq!(to.id, 2);
+        assert!(
+            (3840 - MARGIN * 2 - 1..3840).contains(&x),
+            "entered at x = {}",
+            x
+        );
+    }
+
+    #[test]
+    fn other_protocol_versions_are_refused_readably() {
+        // every version reads the first two variants like this
+        #[derive(Deserialize)]
+        enum AnyVersionStatus {
+            _HandshakeOk,
+            HandshakeErr(String),
+        }
+
+        let server = test_server(ServerConfig::default());
+        let mut buffer = Vec::new();
+
+        for version in [PROTOCOL_VERSION - 1, PROTOCOL_VERSION + 1] {
+            let mut stream = accept(&server, HashMap::new());
+
+            // whatever follows the version in that hello
+            tcp_write(&mut stream, (version, "some hello of another shape")).unwrap();
+            tcp_read(&mut stream, &mut buffer).unwrap();
+
+            match deserialize(&buffer).unwrap() {
+                AnyVersionStatus::HandshakeErr(reason) => {
+                    assert!(reason.contains(&version.to_string()), "{}", reason)
+                }
+                _ => panic!("version {} not refused", version),
+            }
+        }
+    }
 }
@@ -0,0 +1,821 @@
+use std::io::{Error, ErrorKind::*};
+#[cfg(target_os = "linux")]
+use std::{fs, io::Write, thread, time::Duration};
+
+use mouce::common::{MouseButton, ScrollDirection};
+use mouce::{Mouse, MouseActions};
+
+use crate::input::*;
+use crate::utils::*;
+
+/* destination of the input events a client receives from the server */
+pub trait InputSink {
+    /// current cursor position in local system coordinates
+    fn position(&self) -> Result<(i32, i32), Error>;
+    /// move the cursor to the absolute position in local system coordinates
+    fn warp(&mut self, x: i32, y: i32) -> Result<(), Error>;
+    /// move the cursor relative to its current position
+    fn motion(&mut self, dx: i32, dy: i32) -> Result<(), Error>;
+    fn button(&mut self, button: Button, pressed: bool) -> Result<(), Error>;
+    fn scroll(&mut self, delta: ScrollDelta) -> Result<(), Error>;
+    fn key(&mut self, input: KeyInput, pressed: bool) -> Result<(), Error>;
+}
+
+/* injects into the OS; mouce for the pointer, XTest for keys and extra buttons on linux,
+ * a uinput wheel for smooth scrolling on linux and native calls for the rest elsewhere */
+pub struct SystemSink {
+    mouse: Mouse,
+    scroll: ScrollAccumulator,
+    #[cfg(target_os = "linux")]
+    xtest: Option<XTest>,
+    #[cfg(target_os = "linux")]
+    wheel: Option<Wheel>,
+}
+
+impl SystemSink {
+    pub fn new() -> Result<Self, Error> {
+        check_graphical_session()?;
+
+        Ok(SystemSink {
+            mouse: Mouse::new(),
+            scroll: ScrollAccumulator::default(),
+            #[cfg(target_os = "linux")]
+            xtest: match XTest::new() {
+                Ok(xtest) => Some(xtest),
+                Err(e) => {
+                    eprintln!("[WRN] keyboard events will be ignored: {}", e);
+                    None
+                }
+            },
+            #[cfg(target_os = "linux")]
+            wheel: match Wheel::new() {
+                Ok(wheel) => Some(wheel),
+                Err(e) => {
+                    eprintln!("[WRN] scrolling falls back to whole notches: {}", e);
+                    None
+                }
+            },
+        })
+    }
+
+    #[cfg(target_os = "linux")]
+    fn xtest(&self) -> Result<&XTest, Error> {
+        self.xtest
+            .as_ref()
+            .ok_or(Error::new(Unsupported, "XTest is not available"))
+    }
+}
+
+impl InputSink for SystemSink {
+    fn position(&self) -> Result<(i32, i32), Error> {
+        self.mouse.get_position().map_err(mouce_error)
+    }
+
+    fn warp(&mut self, x: i32, y: i32) -> Result<(), Error> {
+        self.mouse.move_to(x, y).map_err(mouce_error)
+    }
+
+    fn motion(&mut self, dx: i32, dy: i32) -> Result<(), Error> {
+        self.mouse.move_relative(dx, dy).map_err(mouce_error)
+    }
+
+    fn button(&mut self, button: Button, pressed: bool) -> Result<(), Error> {
+        let button = match button {
+            Button::Left => MouseButton::Left,
+            Button::Right => MouseButton::Right,
+            Button::Middle => MouseButton::Middle,
+            Button::Back | Button::Forward => return self.side_button(button, pressed),
+        };
+
+        let result = if pressed {
+            self.mouse.press_button(&button)
+        } else {
+            self.mouse.release_button(&button)
+        };
+
+        result.map_err(mouce_error)
+    }
+
+    fn scroll(&mut self, delta: ScrollDelta) -> Result<(), Error> {
+        match self.smooth_scroll(delta) {
+            Some(result) => result,
+            None => self.notch_scroll(delta),
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    fn key(&mut self, input: KeyInput, pressed: bool) -> Result<(), Error> {
+        self.xtest()?.key(&input, pressed)
+    }
+
+    // clients on these platforms leave Capability::Keyboard out; the server sends no keys
+    #[cfg(not(target_os = "linux"))]
+    fn key(&mut self, _input: KeyInput, _pressed: bool) -> Result<(), Error> {
+        Err(Error::new(
+            Unsupported,
+            "keyboard injection is not supported on this platform",
+        ))
+    }
+}
+
+/* scrolling and the back/forward buttons, which mouce cannot do */
+impl SystemSink {
+    /* wheel notches only; anything below a notch waits for more */
+    fn notch_scroll(&mut self, delta: ScrollDelta) -> Result<(), Error> {
+        let (x, y) = self.scroll.push(delta);
+
+        let notches = [
+            (ScrollDirection::Right, x),
+            (ScrollDirection::Left, -x),
+            (ScrollDirection::Up, y),
+            (ScrollDirection::Down, -y),
+        ];
+
+        for (direction, cnt) in notches {
+            for _ in 0..cnt {
+                self.mouse.scroll_wheel(&direction).map_err(mouce_error)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /* fractions of a notch go to the uinput wheel as they are; None if there is none */
+    #[cfg(target_os = "linux")]
+    fn smooth_scroll(&mut self, delta: ScrollDelta) -> Option<Result<(), Error>> {
+        self.wheel.as_mut().map(|x| x.scroll(delta))
+    }
+
+    /* the wheel takes 1/120 notches */
+    #[cfg(target_os = "windows")]
+    fn smooth_scroll(&mut self, delta: ScrollDelta) -> Option<Result<(), Error>> {
+        let (x, y) = self.scroll.push(ScrollDelta {
+            x: delta.x * win::WHEEL_DELTA,
+            y: delta.y * win::WHEEL_DELTA,
+        });
+
+        let mut result = Ok(());
+
+        if y != 0 {
+            result = result.and_then(|_| win::send(win::MOUSEEVENTF_WHEEL, y));
+        }
+
+        if x != 0 {
+            result = result.and_then(|_| win::send(win::MOUSEEVENTF_HWHEEL, x));
+        }
+
+        Some(result)
+    }
+
+    /* scroll events in pixels, like a trackpad */
+    #[cfg(target_os = "macos")]
+    fn smooth_scroll(&mut self, delta: ScrollDelta) -> Option<Result<(), Error>> {
+        let (x, y) = self.scroll.push(ScrollDelta {
+            x: delta.x * PIXELS_PER_LINE,
+            y: delta.y * PIXELS_PER_LINE,
+        });
+
+        if x == 0 && y == 0 {
+            return Some(Ok(()));
+        }
+
+        Some(mac::scroll(x, y))
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "windows", target_os = "macos")))]
+    fn smooth_scroll(&mut self, _delta: ScrollDelta) -> Option<Result<(), Error>> {
+        None
+    }
+
+    #[cfg(target_os = "linux")]
+    fn side_button(&mut self, button: Button, pressed: bool) -> Result<(), Error> {
+        let button = if button == Button::Back { 8 } else { 9 };
+        self.xtest()?.button(button, pressed)
+    }
+
+    #[cfg(target_os = "windows")]
+    fn side_button(&mut self, button: Button, pressed: bool) -> Result<(), Error> {
+        let flags = if pressed {
+            win::MOUSEEVENTF_XDOWN
+        } else {
+            win::MOUSEEVENTF_XUP
+        };
+
+        let button = if button == Button::Back {
+            win::XBUTTON1
+        } else {
+            win::XBUTTON2
+        };
+
+        win::send(flags, button)
+    }
+
+    #[cfg(target_os = "macos")]
+    fn side_button(&mut self, button: Button, pressed: bool) -> Result<(), Error> {
+        let (x, y) = self.position()?;
+        let button = if button == Button::Back { 3 } else { 4 };
+        mac::button(x, y, button, pressed)
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "windows", target_os = "macos")))]
+    fn side_button(&mut self, button: Button, _pressed: bool) -> Result<(), Error> {
+        Err(Error::new(
+            Unsupported,
+            format!("{:?} button is not supported", button),
+        ))
+    }
+}
+
+fn mouce_error(e: mouce::error::Error) -> Error {
+    Error::new(Other, format!("mouce: {}", e))
+}
+
+/* everything a sink was asked to do, in order */
+#[derive(Debug, Clone, PartialEq)]
+pub enum SinkEvent {
+    Warp(i32, i32),
+    Motion(i32, i32),
+    Button(Button, bool),
+    Scroll(ScrollDelta),
+    Key(Key, bool),
+}
+
+/* records injected events instead of touching the OS */
+#[derive(Debug, Default)]
+pub struct RecordingSink {
+    pub events: Vec<SinkEvent>,
+    pub position: (i32, i32),
+}
+
+impl InputSink for RecordingSink {
+    fn position(&self) -> Result<(i32, i32), Error> {
+        Ok(self.position)
+    }
+
+    fn warp(&mut self, x: i32, y: i32) -> Result<(), Error> {
+        self.events.push(SinkEvent::Warp(x, y));
+        self.position = (x, y);
+        Ok(())
+    }
+
+    fn motion(&mut self, dx: i32, dy: i32) -> Result<(), Error> {
+        self.events.push(SinkEvent::Motion(dx, dy));
+        self.position = (self.position.0 + dx, self.position.1 + dy);
+        Ok(())
+    }
+
+    fn button(&mut self, button: Button, pressed: bool) -> Result<(), Error> {
+        self.events.push(SinkEvent::Button(button, pressed));
+        Ok(())
+    }
+
+    fn scroll(&mut self, delta: ScrollDelta) -> Result<(), Error> {
+        self.events.push(SinkEvent::Scroll(delta));
+        Ok(())
+    }
+
+    fn key(&mut self, input: KeyInput, pressed: bool) -> Result<(), Error> {
+        self.events.push(SinkEvent::Key(input.key, pressed));
+        Ok(())
+    }
+}
+
+/* for clients without a screen, like ones started with --virtual; events go nowhere,
+ * only the cursor position is kept so the cursor can still leave */
+#[derive(Debug, Default)]
+pub struct LogSink {
+    position: (i32, i32),
+}
+
+impl InputSink for LogSink {
+    fn position(&self) -> Result<(i32, i32), Error> {
+        Ok(self.position)
+    }
+
+    fn warp(&mut self, x: i32, y: i32) -> Result<(), Error> {
+        self.position = (x, y);
+        Ok(())
+    }
+
+    fn motion(&mut self, dx: i32, dy: i32) -> Result<(), Error> {
+        self.position = (self.position.0 + dx, self.position.1 + dy);
+        Ok(())
+    }
+
+    fn button(&mut self, _button: Button, _pressed: bool) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn scroll(&mut self, _delta: ScrollDelta) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn key(&mut self, _input: KeyInput, _pressed: bool) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+/* XTest connection for the events mouce cannot inject */
+#[cfg(target_os = "linux")]
+struct XTest {
+    xlib: x11_dl::xlib::Xlib,
+    xtest: x11_dl::xtest::Xf86vmode,
+    display: *mut x11_dl::xlib::Display,
+}
+
+#[cfg(target_os = "linux")]
+impl XTest {
+    fn new() -> Result<XTest, Error> {
+        let xlib = x11_dl::xlib::Xlib::open()
+            .map_err(|e| Error::new(Unsupported, format!("libX11 not found: {}", e)))?;
+        let xtest = x11_dl::xtest::Xf86vmode::open()
+            .map_err(|e| Error::new(Unsupported, format!("libXtst not found: {}", e)))?;
+
+        let display = unsafe { (xlib.XOpenDisplay)(std::ptr::null()) };
+
+        if display.is_null() {
+            return Err(Error::new(NotConnected, "cannot open X display"));
+        }
+
+        Ok(XTest {
+            xlib,
+            xtest,
+            display,
+        })
+    }
+
+    fn key(&self, input: &KeyInput, pressed: bool) -> Result<(), Error> {
+        let keycode = match input.key.keysym() {
+            Some(sym) => unsafe { (self.xlib.XKeysymToKeycode)(self.display, sym as _) as u32 },
+            // unmapped key; evdev scan codes are offset by 8 in X keycodes
+            None => input.scan + 8,
+        };
+
+        if keycode == 0 {
+            return Err(Error::new(
+                InvalidInput,
+                format!("no keycode for {:?}", input.key),
+            ));
+        }
+
+        unsafe {
+            (self.xtest.XTestFakeKeyEvent)(self.display, keycode, pressed as i32, 0);
+            (self.xlib.XFlush)(self.display);
+        }
+
+        Ok(())
+    }
+
+    fn button(&self, button: u32, pressed: bool) -> Result<(), Error> {
+        unsafe {
+            (self.xtest.XTestFakeButtonEvent)(self.display, button, pressed as i32, 0);
+            (self.xlib.XFlush)(self.display);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Drop for XTest {
+    fn drop(&mut self) {
+        unsafe {
+            (self.xlib.XCloseDisplay)(self.display);
+        }
+    }
+}
+
+/* virtual uinput device with a high resolution wheel; XTest only knows whole notches */
+#[cfg(target_os = "linux")]
+struct Wheel {
+    file: fs::File,
+    hi_res: ScrollAccumulator, // in 1/120 notches
+    notches: (i32, i32),       // hi-res units not yet sent as notches to legacy clients
+}
+
+#[cfg(target_os = "linux")]
+mod uinput {
+    pub const UI_SET_EVBIT: u64 = 0x40045564;
+    pub const UI_SET_KEYBIT: u64 = 0x40045565;
+    pub const UI_SET_RELBIT: u64 = 0x40045566;
+    pub const UI_DEV_SETUP: u64 = 0x405c5503;
+    pub const UI_DEV_CREATE: u64 = 0x5501;
+    pub const UI_DEV_DESTROY: u64 = 0x5502;
+
+    pub const EV_SYN: u16 = 0x00;
+    pub const EV_KEY: u16 = 0x01;
+    pub const EV_REL: u16 = 0x02;
+    pub const SYN_REPORT: u16 = 0x00;
+    pub const BTN_LEFT: u16 = 0x110;
+    pub const REL_X: u16 = 0x00;
+    pub const REL_Y: u16 = 0x01;
+    pub const REL_HWHEEL: u16 = 0x06;
+    pub const REL_WHEEL: u16 = 0x08;
+    pub const REL_WHEEL_HI_RES: u16 = 0x0b;
+    pub const REL_HWHEEL_HI_RES: u16 = 0x0c;
+
+    pub const BUS_VIRTUAL: u16 = 0x06;
+    pub const HI_RES_PER_NOTCH: i32 = 120;
+}
+
+#[cfg(target_os = "linux")]
+impl Wheel {
+    fn new() -> Result<Wheel, Error> {
+        use std::os::unix::io::AsRawFd;
+        use uinput::*;
+
+        let file = fs::OpenOptions::new().write(true).open("/dev/uinput")?;
+        let fd = file.as_raw_fd();
+
+        let mut setup: libc::uinput_setup = unsafe { std::mem::zeroed() };
+        setup.id.bustype = BUS_VIRTUAL;
+
+        for (dst, src) in setup.name.iter_mut().zip(b"transistor wheel") {
+            *dst = *src as libc::c_char;
+        }
+
+        // pointer axes and a button only so that the device is taken for a mouse; never sent
+        let ok = unsafe {
+            libc::ioctl(fd, UI_SET_EVBIT as _, EV_KEY as libc::c_int) >= 0
+                && libc::ioctl(fd, UI_SET_KEYBIT as _, BTN_LEFT as libc::c_int) >= 0
+                && libc::ioctl(fd, UI_SET_EVBIT as _, EV_REL as libc::c_int) >= 0
+                && [
+                    REL_X,
+                    REL_Y,
+                    REL_WHEEL,
+                    REL_HWHEEL,
+                    REL_WHEEL_HI_RES,
+                    REL_HWHEEL_HI_RES,
+                ]
+                .iter()
+                .all(|x| libc::ioctl(fd, UI_SET_RELBIT as _, *x as libc::c_int) >= 0)
+                && libc::ioctl(fd, UI_DEV_SETUP as _, &setup as *const libc::uinput_setup) >= 0
+                && libc::ioctl(fd, UI_DEV_CREATE as _) >= 0
+        };
+
+        if !ok {
+            return Err(Error::last_os_error());
+        }
+
+        // events sent before the compositor picks the device up are lost
+        thread::sleep(Duration::from_millis(300));
+
+        Ok(Wheel {
+            file,
+            hi_res: ScrollAccumulator::default(),
+            notches: (0, 0),
+        })
+    }
+
+    fn scroll(&mut self, delta: ScrollDelta) -> Result<(), Error> {
+        use uinput::*;
+
+        let (x, y) = self.hi_res.push(ScrollDelta {
+            x: delta.x * HI_RES_PER_NOTCH as f32,
+            y: delta.y * HI_RES_PER_NOTCH as f32,
+        });
+
+        if x == 0 && y == 0 {
+            return Ok(());
+        }
+
+        self.notches.0 += x;
+        self.notches.1 += y;
+
+        let notch_x = self.notches.0 / HI_RES_PER_NOTCH;
+        let notch_y = self.notches.1 / HI_RES_PER_NOTCH;
+
+        self.notches.0 -= notch_x * HI_RES_PER_NOTCH;
+        self.notches.1 -= notch_y * HI_RES_PER_NOTCH;
+
+        // hi-res aware clients read the fine axes; the others still get whole notches
+        let events = [
+            (EV_REL, REL_WHEEL_HI_RES, y),
+            (EV_REL, REL_HWHEEL_HI_RES, x),
+            (EV_REL, REL_WHEEL, notch_y),
+            (EV_REL, REL_HWHEEL, notch_x),
+        ];
+
+        for (kind, code, value) in events {
+            if value != 0 {
+                self.emit(kind, code, value)?;
+            }
+        }
+
+        self.emit(EV_SYN, SYN_REPORT, 0)
+    }
+
+    fn emit(&mut self, kind: u16, code: u16, value: i32) -> Result<(), Error> {
+        let event = libc::input_event {
+            time: libc::timeval {
+                tv_sec: 0,
+                tv_usec: 0,
+            },
+            type_: kind,
+            code,
+            value,
+        };
+
+        let bytes = unsafe {
+            std::slice::from_raw_parts(
+                &event as *const libc::input_event as *const u8,
+                std::mem::size_of::<libc::input_event>(),
+            )
+        };
+
+        self.file.write_all(bytes)
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Drop for Wheel {
+    fn drop(&mut self) {
+        use std::os::unix::io::AsRawFd;
+
+        unsafe {
+            libc::ioctl(self.file.as_raw_fd(), uinput::UI_DEV_DESTROY as _);
+        }
+    }
+}
+
+/* SendInput for wheel fractions and the side buttons */
+#[cfg(target_os = "windows")]
+mod win {
+    use std::io::Error;
+
+    pub const MOUSEEVENTF_XDOWN: u32 = 0x0080;
+    pub const MOUSEEVENTF_XUP: u32 = 0x0100;
+    pub const MOUSEEVENTF_WHEEL: u32 = 0x0800;
+    pub const MOUSEEVENTF_HWHEEL: u32 = 0x1000;
+    pub const XBUTTON1: i32 = 0x0001;
+    pub const XBUTTON2: i32 = 0x0002;
+    pub const WHEEL_DELTA: f32 = 120.0;
+
+    const INPUT_MOUSE: u32 = 0;
+
+    #[repr(C)]
+    struct MouseInput {
+        dx: i32,
+        dy: i32,
+        mouse_data: u32,
+        flags: u32,
+        time: u32,
+        extra_info: usize,
+    }
+
+    #[repr(C)]
+    struct Input {
+        kind: u32,
+        mi: MouseInput,
+    }
+
+    #[link(name = "user32")]
+    extern "system" {
+        fn SendInput(count: u32, inputs: *const Input, size: i32) -> u32;
+    }
+
+    /* mouse_data is signed for the wheel and a button mask for the side buttons */
+    pub fn send(flags: u32, data: i32) -> Result<(), Error> {
+        let input = Input {
+            kind: INPUT_MOUSE,
+            mi: MouseInput {
+                dx: 0,
+                dy: 0,
+                mouse_data: data as u32,
+                flags,
+                time: 0,
+                extra_info: 0,
+            },
+        };
+
+        let sent = unsafe { SendInput(1, &input, std::mem::size_of::<Input>() as i32) };
+
+        if sent != 1 {
+            return Err(Error::last_os_error());
+        }
+
+        Ok(())
+    }
+}
+
+/* Quartz events for pixel scrolling and the side buttons */
+#[cfg(target_os = "macos")]
+mod mac {
+    use std::ffi::c_void;
+    use std::io::{Error, ErrorKind::*};
+
+    type CGEventRef = *mut c_void;
+
+    #[repr(C)]
+    #[derive(Clone, Copy)]
+    struct CGPoint {
+        x: f64,
+        y: f64,
+    }
+
+    const SCROLL_UNIT_PIXEL: u32 = 0;
+    const OTHER_MOUSE_DOWN: u32 = 25;
+    const OTHER_MOUSE_UP: u32 = 26;
+    const HID_EVENT_TAP: u32 = 0;
+
+    #[link(name = "CoreGraphics", kind = "framework")]
+    extern "C" {
+        fn CGEventCreateScrollWheelEvent(
+            source: *mut c_void,
+            units: u32,
+            wheel_count: u32,
+            wheel1: i32,
+            ...
+        ) -> CGEventRef;
+        fn CGEventCreateMouseEvent(
+            source: *mut c_void,
+            kind: u32,
+            position: CGPoint,
+            button: u32,
+        ) -> CGEventRef;
+        fn CGEventPost(tap: u32, event: CGEventRef);
+    }
+
+    #[link(name = "CoreFoundation", kind = "framework")]
+    extern "C" {
+        fn CFRelease(cf: *const c_void);
+    }
+
+    fn post(event: CGEventRef) -> Result<(), Error> {
+        if event.is_null() {
+            return Err(Error::new(Other, "cannot create a Quartz event"));
+        }
+
+        unsafe {
+            CGEventPost(HID_EVENT_TAP, event);
+            CFRelease(event);
+        }
+
+        Ok(())
+    }
+
+    /* wheel2 scrolls left when positive */
+    pub fn scroll(x: i32, y: i32) -> Result<(), Error> {
+        post(unsafe {
+            CGEventCreateScrollWheelEvent(std::ptr::null_mut(), SCROLL_UNIT_PIXEL, 2, y, -x)
+        })
+    }
+
+    pub fn button(x: i32, y: i32, button: u32, pressed: bool) -> Result<(), Error> {
+        let kind = if pressed {
+            OTHER_MOUSE_DOWN
+        } else {
+            OTHER_MOUSE_UP
+        };
+
+        let position = CGPoint {
+            x: x as f64,
+            y: y as f64,
+        };
+
+        post(unsafe { CGEventCreateMouseEvent(std::ptr::null_mut(), kind, position, button) })
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Key {
+    fn keysym(&self) -> Option<u32> {
+        use x11_dl::keysym::*;
+
+        let sym = match self {
+            Key::A => XK_a,
+            Key::B => XK_b,
+            Key::C => XK_c,
+            Key::D => XK_d,
+            Key::E => XK_e,
+            Key::F => XK_f,
+            Key::G => XK_g,
+            Key::H => XK_h,
+            Key::I => XK_i,
+            Key::J => XK_j,
+            Key::K => XK_k,
+            Key::L => XK_l,
+            Key::M => XK_m,
+            Key::N => XK_n,
+            Key::O => XK_o,
+            Key::P => XK_p,
+            Key::Q => XK_q,
+            Key::R => XK_r,
+            Key::S => XK_s,
+            Key::T => XK_t,
+            Key::U => XK_u,
+            Key::V => XK_v,
+            Key::W => XK_w,
+            Key::X => XK_x,
+            Key::Y => XK_y,
+            Key::Z => XK_z,
+            Key::Num0 => XK_0,
+            Key::Num1 => XK_1,
+            Key::Num2 => XK_2,
+            Key::Num3 => XK_3,
+            Key::Num4 => XK_4,
+            Key::Num5 => XK_5,
+            Key::Num6 => XK_6,
+            Key::Num7 => XK_7,
+            Key::Num8 => XK_8,
+            Key::Num9 => XK_9,
+            Key::F1 => XK_F1,
+            Key::F2 => XK_F2,
+            Key::F3 => XK_F3,
+            Key::F4 => XK_F4,
+            Key::F5 => XK_F5,
+            Key::F6 => XK_F6,
+            Key::F7 => XK_F7,
+            Key::F8 => XK_F8,
+            Key::F9 => XK_F9,
+            Key::F10 => XK_F10,
+            Key::F11 => XK_F11,
+            Key::F12 => XK_F12,
+            Key::F13 => XK_F13,
+            Key::F14 => XK_F14,
+            Key::F15 => XK_F15,
+            Key::F16 => XK_F16,
+            Key::F17 => XK_F17,
+            Key::F18 => XK_F18,
+            Key::F19 => XK_F19,
+            Key::F20 => XK_F20,
+            Key::F21 => XK_F21,
+            Key::F22 => XK_F22,
+            Key::F23 => XK_F23,
+            Key::F24 => XK_F24,
+            Key::Escape => XK_Escape,
+            Key::PrintScreen => XK_Print,
+            Key::ScrollLock => XK_Scroll_Lock,
+            Key::Pause => XK_Pause,
+            Key::Insert => XK_Insert,
+            Key::Delete => XK_Delete,
+            Key::Home => XK_Home,
+            Key::End => XK_End,
+            Key::PageUp => XK_Prior,
+            Key::PageDown => XK_Next,
+            Key::Left => XK_Left,
+            Key::Up => XK_Up,
+            Key::Right => XK_Right,
+            Key::Down => XK_Down,
+            Key::Backspace => XK_BackSpace,
+            Key::Enter => XK_Return,
+            Key::Space => XK_space,
+            Key::Tab => XK_Tab,
+            Key::CapsLock => XK_Caps_Lock,
+            Key::NumLock => XK_Num_Lock,
+            Key::Numpad0 => XK_KP_0,
+            Key::Numpad1 => XK_KP_1,
+            Key::Numpad2 => XK_KP_2,
+            Key::Numpad3 => XK_KP_3,
+            Key::Numpad4 => XK_KP_4,
+            Key::Numpad5 => XK_KP_5,
+            Key::Numpad6 => XK_KP_6,
+            Key::Numpad7 => XK_KP_7,
+            Key::Numpad8 => XK_KP_8,
+            Key::Numpad9 => XK_KP_9,
+            Key::NumpadAdd => XK_KP_Add,
+            Key::NumpadSubtract => XK_KP_Subtract,
+            Key::NumpadMultiply => XK_KP_Multiply,
+            Key::NumpadDivide => XK_KP_Divide,
+            Key::NumpadDecimal => XK_KP_Decimal,
+            Key::NumpadEnter => XK_KP_Enter,
+            Key::NumpadEquals => XK_KP_Equal,
+            Key::Apostrophe => XK_apostrophe,
+            Key::Backslash => XK_backslash,
+            Key::Comma => XK_comma,
+            Key::Equals => XK_equal,
+            Key::Grave => XK_grave,
+            Key::LBracket => XK_bracketleft,
+            Key::RBracket => XK_bracketright,
+            Key::Minus => XK_minus,
+            Key::Period => XK_period,
+            Key::Semicolon => XK_semicolon,
+            Key::Slash => XK_slash,
+            Key::IntlBackslash => XK_less,
+            Key::LShift => XK_Shift_L,
+            Key::RShift => XK_Shift_R,
+            Key::LControl => XK_Control_L,
+            Key::RControl => XK_Control_R,
+            Key::LAlt => XK_Alt_L,
+            Key::RAlt => XK_Alt_R,
+            Key::LMeta => XK_Super_L,
+            Key::RMeta => XK_Super_R,
+            Key::Menu => XK_Menu,
+            Key::Kana => XK_Katakana,
+            Key::Kanji => XK_Kanji,
+            Key::Convert => XK_Henkan,
+            Key::NoConvert => XK_Muhenkan,
+            Key::Yen => XK_yen,
+            Key::Mute => XF86XK_AudioMute,
+            Key::VolumeDown => XF86XK_AudioLowerVolume,
+            Key::VolumeUp => XF86XK_AudioRaiseVolume,
+            Key::PlayPause => XF86XK_AudioPlay,
+            Key::MediaStop => XF86XK_AudioStop,
+            Key::NextTrack => XF86XK_AudioNext,
+            Key::PrevTrack => XF86XK_AudioPrev,
+            Key::Unknown => return None,
+        };
+
+        Some(sym)
+    }
+}
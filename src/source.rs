@@ -0,0 +1,405 @@
+use std::collections::VecDeque;
+use std::io::{Error, ErrorKind::*};
+use std::sync::{
+    mpsc::{channel, Receiver, RecvTimeoutError, Sender},
+    Arc, Mutex,
+};
+use std::time::Duration;
+
+use mouce::{Mouse, MouseActions};
+use winit::dpi::{PhysicalPosition, PhysicalSize};
+use winit::event::{DeviceEvent, ElementState, Event};
+use winit::event_loop::{
+    ControlFlow, DeviceEventFilter, EventLoop, EventLoopBuilder, EventLoopProxy,
+};
+use winit::platform::run_return::EventLoopExtRunReturn;
+use winit::window::{CursorGrabMode, Window, WindowBuilder, WindowLevel};
+
+use crate::input::*;
+use crate::utils::*;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum InputEvent {
+    Position(i32, i32),
+    // raw pointer motion, before the OS applies acceleration or clamps to the screen
+    Motion(i32, i32),
+    Key(KeyInput, bool),
+    Button(Button, bool),
+    Scroll(ScrollDelta),
+    // nothing happened; asked for through Cursor::wake to look at the pointer again
+    Wake,
+}
+
+pub type InputCallback = Box<dyn FnMut(InputEvent) + Send>;
+
+/* origin of the input events the server captures */
+pub trait InputSource {
+    /// current absolute cursor position in system coordinates
+    fn position(&self) -> Result<(i32, i32), Error>;
+    /// handle to place the cursor from other threads while the source runs
+    fn cursor(&self) -> Box<dyn Cursor>;
+    /// deliver events to the callback; blocks until the source is exhausted
+    fn run(&mut self, callback: InputCallback) -> Result<(), Error>;
+}
+
+pub trait Cursor: Send {
+    /// move the cursor to the absolute position in system coordinates
+    fn warp(&mut self, x: i32, y: i32) -> Result<(), Error>;
+    /// hide and hold the cursor in place; local apps stop receiving pointer and key input
+    fn confine(&mut self, confine: bool) -> Result<(), Error>;
+    /// have the source deliver InputEvent::Wake to its callback
+    fn wake(&mut self) -> Result<(), Error>;
+}
+
+/* requests to the event loop thread, which owns the capture window */
+#[derive(Debug, Clone, Copy)]
+enum Control {
+    Warp(i32, i32),
+    Confine(bool),
+    Wake,
+}
+
+/* captures from the OS; winit raw device events, mouce for the cursor position */
+pub struct SystemSource {
+    mouse: Mouse,
+    event_loop: EventLoop<Control>,
+    window: Window,
+}
+
+impl SystemSource {
+    pub fn new() -> Result<Self, Error> {
+        check_graphical_session()?;
+
+        let event_loop = EventLoopBuilder::with_user_event().build();
+
+        // receive raw device events regardless of which window has the focus
+        event_loop.set_device_event_filter(DeviceEventFilter::Never);
+
+        // tiny window that takes the cursor and keyboard focus while a remote display is active
+        let window = WindowBuilder::new()
+            .with_title("transistor")
+            .with_inner_size(PhysicalSize::new(1, 1))
+            .with_decorations(false)
+            .with_resizable(false)
+            .with_transparent(true)
+            .with_visible(false)
+            .with_window_level(WindowLevel::AlwaysOnTop)
+            .build(&event_loop)
+            .map_err(|e| Error::new(Other, format!("cannot create capture window: {}", e)))?;
+
+        Ok(SystemSource {
+            mouse: Mouse::new(),
+            event_loop,
+            window,
+        })
+    }
+}
+
+/* cursor handle of SystemSource; everything runs on the event loop thread in order */
+pub struct SystemCursor {
+    proxy: EventLoopProxy<Control>,
+}
+
+impl SystemCursor {
+    fn send(&self, control: Control) -> Result<(), Error> {
+        self.proxy
+            .send_event(control)
+            .map_err(|_| Error::new(BrokenPipe, "input capture is not running"))
+    }
+}
+
+impl Cursor for SystemCursor {
+    fn warp(&mut self, x: i32, y: i32) -> Result<(), Error> {
+        self.send(Control::Warp(x, y))
+    }
+
+    fn confine(&mut self, confine: bool) -> Result<(), Error> {
+        self.send(Control::Confine(confine))
+    }
+
+    fn wake(&mut self) -> Result<(), Error> {
+        self.send(Control::Wake)
+    }
+}
+
+fn control(mouse: &Mouse, window: &Window, control: Control) -> Result<(), Error> {
+    match control {
+        Control::Warp(x, y) => mouse
+            .move_to(x, y)
+            .map_err(|e| Error::new(Other, format!("cannot move cursor: {}", e))),
+        Control::Confine(true) => {
+            let (x, y) = mouse
+                .get_position()
+                .map_err(|e| Error::new(Other, format!("cannot get cursor position: {}", e)))?;
+
+            // put the window under the cursor and hold it there
+            window.set_outer_position(PhysicalPosition::new(x, y));
+            window.set_visible(true);
+            window.focus_window();
+            window.set_cursor_visible(false);
+
+            // not every platform supports both modes
+            window
+                .set_cursor_grab(CursorGrabMode::Confined)
+                .or_else(|_| window.set_cursor_grab(CursorGrabMode::Locked))
+                .map_err(|e| Error::new(Unsupported, format!("cannot grab cursor: {}", e)))
+        }
+        Control::Confine(false) => {
+            let result = window
+                .set_cursor_grab(CursorGrabMode::None)
+                .map_err(|e| Error::new(Other, format!("cannot release cursor: {}", e)));
+
+            window.set_cursor_visible(true);
+            window.set_visible(false);
+
+            result
+        }
+        Control::Wake => Ok(()), // for the callback, not the window
+    }
+}
+
+impl InputSource for SystemSource {
+    fn position(&self) -> Result<(i32, i32), Error> {
+        self.mouse
+            .get_position()
+            .map_err(|e| Error::new(Other, format!("cannot get cursor position: {}", e)))
+    }
+
+    fn cursor(&self) -> Box<dyn Cursor> {
+        Box::new(SystemCursor {
+            proxy: self.event_loop.create_proxy(),
+        })
+    }
+
+    fn run(&mut self, mut callback: InputCallback) -> Result<(), Error> {
+        /* pointer, keyboard, button and wheel; blocks until the event loop exits */
+        let mut modifiers = Modifiers::default();
+        let mut remain = (0.0, 0.0);
+        let mouse = &self.mouse;
+        let window = &self.window;
+
+        self.event_loop.run_return(move |event, _, control_flow| {
+            *control_flow = ControlFlow::Wait;
+
+            let event = match event {
+                Event::DeviceEvent { event, .. } => event,
+                Event::UserEvent(Control::Wake) => return callback(InputEvent::Wake),
+                Event::UserEvent(request) => {
+                    if let Err(e) = control(mouse, window, request) {
+                        eprintln!("[ERR] cursor control failed: {}", e);
+                    }
+
+                    return;
+                }
+                _ => return,
+            };
+
+            let event = match event {
+                DeviceEvent::MouseMotion { delta } => {
+                    // keep the fractions of high-resolution devices for the next event
+                    remain.0 += delta.0;
+                    remain.1 += delta.1;
+
+                    let (dx, dy) = (remain.0.trunc(), remain.1.trunc());
+                    remain.0 -= dx;
+                    remain.1 -= dy;
+
+                    if dx != 0.0 || dy != 0.0 {
+                        callback(InputEvent::Motion(dx as i32, dy as i32));
+                    }
+
+                    // the pointer position is not part of raw events
+                    match mouse.get_position() {
+                        Ok((x, y)) => callback(InputEvent::Position(x, y)),
+                        Err(e) => eprintln!("[ERR] cannot get cursor position: {}", e),
+                    }
+
+                    return;
+                }
+                DeviceEvent::Key(input) => {
+                    let key = input.virtual_keycode.map_or(Key::Unknown, Key::from);
+                    let pressed = input.state == ElementState::Pressed;
+
+                    modifiers.update(key, pressed);
+
+                    InputEvent::Key(
+                        KeyInput {
+                            key,
+                            scan: input.scancode,
+                            modifiers,
+                        },
+                        pressed,
+                    )
+                }
+                DeviceEvent::Button { button, state } => {
+                    // wheel buttons of legacy devices are reported as MouseWheel too
+                    let button = match Button::from_raw(button) {
+                        Some(button) => button,
+                        None => return,
+                    };
+
+                    InputEvent::Button(button, state == ElementState::Pressed)
+                }
+                DeviceEvent::MouseWheel { delta } => InputEvent::Scroll(ScrollDelta::from(delta)),
+                _ => return,
+            };
+
+            callback(event);
+        });
+
+        Ok(())
+    }
+}
+
+/* how often a live MockSource without new events looks for wakes */
+const WAKE_POLL: Duration = Duration::from_millis(10);
+
+/* scriptable source for running the server without real input devices */
+pub struct MockSource {
+    position: Arc<Mutex<(i32, i32)>>,
+    confined: Arc<Mutex<bool>>,
+    wakes: Arc<Mutex<u32>>, // requested through the cursor, not delivered yet
+    script: VecDeque<InputEvent>,
+    live: Option<Receiver<InputEvent>>,
+}
+
+impl MockSource {
+    pub fn new(x: i32, y: i32) -> Self {
+        MockSource {
+            position: Arc::new(Mutex::new((x, y))),
+            confined: Arc::new(Mutex::new(false)),
+            wakes: Arc::new(Mutex::new(0)),
+            script: VecDeque::new(),
+            live: None,
+        }
+    }
+
+    /// source that also delivers everything sent to the returned sender until it is dropped
+    pub fn live(x: i32, y: i32) -> (Sender<InputEvent>, Self) {
+        let (tx, rx) = channel();
+        let mut source = MockSource::new(x, y);
+        source.live = Some(rx);
+
+        (tx, source)
+    }
+
+    pub fn is_confined(&self) -> bool {
+        *self.confined.lock().unwrap()
+    }
+
+    fn take_wake(&self) -> bool {
+        let mut wakes = self.wakes.lock().unwrap();
+        let woken = *wakes > 0;
+        *wakes = wakes.saturating_sub(1);
+        woken
+    }
+
+    pub fn then(mut self, event: InputEvent) -> Self {
+        self.script.push_back(event);
+        self
+    }
+
+    /// cursor stream moving in straight steps from the last position to (x, y)
+    pub fn move_to(mut self, x: i32, y: i32, steps: i32) -> Self {
+        let (sx, sy) = match self.script.iter().rev().find_map(|e| match e {
+            InputEvent::Position(x, y) => Some((*x, *y)),
+            _ => None,
+        }) {
+            Some(pos) => pos,
+            None => *self.position.lock().unwrap(),
+        };
+
+        let steps = steps.max(1);
+
+        for i in 1..=steps {
+            self.script.push_back(InputEvent::Position(
+                sx + (x - sx) * i / steps,
+                sy + (y - sy) * i / steps,
+            ));
+        }
+
+        self
+    }
+
+    /// raw pointer motion of (dx, dy) split into equal steps
+    pub fn motion(mut self, dx: i32, dy: i32, steps: i32) -> Self {
+        let steps = steps.max(1);
+
+        for i in 1..=steps {
+            self.script.push_back(InputEvent::Motion(
+                dx * i / steps - dx * (i - 1) / steps,
+                dy * i / steps - dy * (i - 1) / steps,
+            ));
+        }
+
+        self
+    }
+}
+
+impl InputSource for MockSource {
+    fn position(&self) -> Result<(i32, i32), Error> {
+        Ok(*self.position.lock().unwrap())
+    }
+
+    fn cursor(&self) -> Box<dyn Cursor> {
+        Box::new(MockCursor {
+            position: self.position.clone(),
+            confined: self.confined.clone(),
+            wakes: self.wakes.clone(),
+        })
+    }
+
+    /// wakes requested after the script and the live events are over are dropped
+    fn run(&mut self, mut callback: InputCallback) -> Result<(), Error> {
+        let live = self.live.take();
+
+        loop {
+            let event = if self.take_wake() {
+                InputEvent::Wake
+            } else if let Some(event) = self.script.pop_front() {
+                event
+            } else {
+                match &live {
+                    Some(live) => match live.recv_timeout(WAKE_POLL) {
+                        Ok(event) => event,
+                        Err(RecvTimeoutError::Timeout) => continue,
+                        Err(RecvTimeoutError::Disconnected) => break,
+                    },
+                    None => break,
+                }
+            };
+
+            if let InputEvent::Position(x, y) = event {
+                *self.position.lock().unwrap() = (x, y);
+            }
+
+            callback(event);
+        }
+
+        Ok(())
+    }
+}
+
+/* warps and confinement of a MockSource cursor show up in its state */
+pub struct MockCursor {
+    position: Arc<Mutex<(i32, i32)>>,
+    confined: Arc<Mutex<bool>>,
+    wakes: Arc<Mutex<u32>>,
+}
+
+impl Cursor for MockCursor {
+    fn warp(&mut self, x: i32, y: i32) -> Result<(), Error> {
+        *self.position.lock().unwrap() = (x, y);
+        Ok(())
+    }
+
+    fn confine(&mut self, confine: bool) -> Result<(), Error> {
+        *self.confined.lock().unwrap() = confine;
+        Ok(())
+    }
+
+    fn wake(&mut self) -> Result<(), Error> {
+        *self.wakes.lock().unwrap() += 1;
+        Ok(())
+    }
+}
@@ -0,0 +1,349 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io::{Error, ErrorKind::*, Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use rustls::client::{ServerCertVerified, ServerCertVerifier};
+use rustls::{Certificate, ClientConnection, Connection, PrivateKey, ServerConnection, ServerName};
+use sha2::{Digest, Sha256};
+
+use crate::*;
+
+/* the certificate is self-signed; clients trust it by its fingerprint, not by its name */
+const SERVER_NAME: &str = "transistor";
+
+/* TLS over a tcp connection; clones share the session so one side can read while another writes */
+pub struct SecureStream {
+    conn: Arc<Mutex<Connection>>,
+    tcp: TcpStream,
+}
+
+impl SecureStream {
+    /// server side of the handshake
+    pub fn accept(mut tcp: TcpStream, config: &Arc<rustls::ServerConfig>) -> Result<Self, Error> {
+        let conn = ServerConnection::new(config.clone()).map_err(tls_error)?;
+        let mut conn = Connection::Server(conn);
+
+        conn.complete_io(&mut tcp)?;
+
+        Ok(SecureStream {
+            conn: Arc::new(Mutex::new(conn)),
+            tcp,
+        })
+    }
+
+    /// client side of the handshake; the server certificate is pinned on first use
+    pub fn connect(server: &str) -> Result<Self, Error> {
+        let file = config_dir!("client").join("known_servers.json");
+        let path = file.as_os_str().to_str().unwrap().to_string();
+
+        let mut known = load_known_servers(&file)?;
+
+        let pinned = known.get(server).cloned();
+
+        let verifier = Arc::new(PinnedServer {
+            pinned: pinned.clone(),
+            seen: Mutex::new(None),
+        });
+
+        let mut conn = client_connection(verifier.clone())?;
+
+        let mut tcp = TcpStream::connect(server)?;
+        let handshake = conn.complete_io(&mut tcp);
+
+        let seen = verifier.seen.lock().unwrap().clone();
+
+        match (pinned, seen) {
+            (Some(pinned), Some(seen)) if pinned != seen => {
+                eprintln!("[WRN] ################################################");
+                eprintln!("[WRN] the certificate of server {} has changed!", server);
+                eprintln!("[WRN]   pinned:    {}", pinned);
+                eprintln!("[WRN]   presented: {}", seen);
+                eprintln!("[WRN] someone may be intercepting the connection.");
+                eprintln!("[WRN] if the server was reinstalled on purpose,");
+                eprintln!("[WRN] remove its entry from {}", path);
+                eprintln!("[WRN] ################################################");
+
+                return Err(Error::new(
+                    PermissionDenied,
+                    "[ERR] server certificate does not match the pinned one",
+                ));
+            }
+            (None, Some(seen)) if handshake.is_ok() => {
+                println!("[INF] trusting server {} from now on", server);
+                println!("[INF] certificate fingerprint: {}", seen);
+
+                known.insert(server.to_string(), seen);
+
+                let json = serde_json::to_string_pretty(&known)?;
+
+                if let Err(e) = write_atomic(&file, json.as_bytes()) {
+                    eprintln!("[ERR] failed to write to {}: {}", path, e);
+                }
+            }
+            _ => {}
+        }
+
+        handshake?;
+
+        Ok(SecureStream {
+            conn: Arc::new(Mutex::new(conn)),
+            tcp,
+        })
+    }
+
+    /// client side trusting any certificate; for tests against a server of their own
+    #[cfg(test)]
+    pub fn connect_unpinned(mut tcp: TcpStream) -> Result<Self, Error> {
+        let mut conn = client_connection(Arc::new(PinnedServer {
+            pinned: None,
+            seen: Mutex::new(None),
+        }))?;
+
+        conn.complete_io(&mut tcp)?;
+
+        Ok(SecureStream {
+            conn: Arc::new(Mutex::new(conn)),
+            tcp,
+        })
+    }
+
+    pub fn try_clone(&self) -> Result<Self, Error> {
+        Ok(SecureStream {
+            conn: self.conn.clone(),
+            tcp: self.tcp.try_clone()?,
+        })
+    }
+
+    pub fn peer_addr(&self) -> Result<SocketAddr, Error> {
+        self.tcp.peer_addr()
+    }
+
+    /// keying material exported from the session; the same on both ends of this session only
+    pub fn binding(&self) -> Result<Binding, Error> {
+        self.conn
+            .lock()
+            .unwrap()
+            .export_keying_material([0u8; 32], b"EXPORTER-transistor-client-auth", None)
+            .map_err(tls_error)
+    }
+
+    /// applies to every clone of the stream
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> Result<(), Error> {
+        self.tcp.set_read_timeout(timeout)
+    }
+}
+
+impl Read for SecureStream {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        let mut record = [0u8; 4096];
+
+        loop {
+            match self.conn.lock().unwrap().reader().read(buf) {
+                Err(e) if e.kind() == WouldBlock => {}
+                result => return result,
+            }
+
+            // wait for the next records without blocking writers on the session
+            let len = self.tcp.read(&mut record)?;
+
+            let mut conn = self.conn.lock().unwrap();
+            let mut data = &record[..len];
+
+            loop {
+                conn.read_tls(&mut data)?;
+                conn.process_new_packets().map_err(tls_error)?;
+
+                if data.is_empty() {
+                    break;
+                }
+            }
+
+            // alerts and key updates
+            while conn.wants_write() {
+                conn.write_tls(&mut self.tcp)?;
+            }
+        }
+    }
+}
+
+impl Write for SecureStream {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+        let mut conn = self.conn.lock().unwrap();
+        let len = conn.writer().write(buf)?;
+
+        while conn.wants_write() {
+            conn.write_tls(&mut self.tcp)?;
+        }
+
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        self.tcp.flush()
+    }
+}
+
+impl fmt::Debug for SecureStream {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SecureStream")
+            .field("peer", &self.tcp.peer_addr().ok())
+            .finish()
+    }
+}
+
+/* pinned fingerprints by server address; without the file nothing is pinned yet */
+fn load_known_servers(file: &Path) -> Result<HashMap<String, String>, Error> {
+    let path = file.as_os_str().to_str().unwrap();
+
+    // anything but a missing file would silently unpin every server
+    let json = match fs::read_to_string(file) {
+        Ok(json) => json,
+        Err(e) if e.kind() == NotFound => return Ok(HashMap::new()),
+        Err(e) => return Err(Error::new(e.kind(), format!("cannot read {}: {}", path, e))),
+    };
+
+    serde_json::from_str(&json)
+        .map_err(|e| Error::new(InvalidData, format!("invalid {}: {}", path, e)))
+}
+
+/* load the server certificate or create a self-signed one */
+pub fn server_tls_config() -> Result<Arc<rustls::ServerConfig>, Error> {
+    let cert_file = config_dir!("server").join("cert.der");
+    let key_file = config_dir!("server").join("key.der");
+
+    if !cert_file.exists() || !key_file.exists() {
+        let cert = rcgen::generate_simple_self_signed(vec![SERVER_NAME.to_string()])
+            .map_err(|e| Error::new(Other, format!("certificate generation failed: {}", e)))?;
+
+        let der = cert
+            .serialize_der()
+            .map_err(|e| Error::new(Other, format!("certificate generation failed: {}", e)))?;
+
+        fs::write(&cert_file, der)?;
+
+        // the key is for the server's eyes only
+        write_private(&key_file, &cert.serialize_private_key_der())?;
+
+        println!(
+            "[INF] certificate created at {}",
+            cert_file.as_os_str().to_str().unwrap()
+        );
+    }
+
+    let cert = fs::read(&cert_file)?;
+    let key = fs::read(&key_file)?;
+
+    // clients can compare this with what they pinned
+    println!("[INF] certificate fingerprint: {}", fingerprint(&cert));
+
+    let config = rustls::ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_single_cert(vec![Certificate(cert)], PrivateKey(key))
+        .map_err(tls_error)?;
+
+    Ok(Arc::new(config))
+}
+
+/* sha-256 of the DER certificate */
+pub fn fingerprint(der: &[u8]) -> String {
+    let hash: Vec<String> = Sha256::digest(der)
+        .iter()
+        .map(|x| format!("{:02x}", x))
+        .collect();
+
+    format!("SHA256:{}", hash.join(":"))
+}
+
+fn client_connection(verifier: Arc<PinnedServer>) -> Result<Connection, Error> {
+    let config = rustls::ClientConfig::builder()
+        .with_safe_defaults()
+        .with_custom_certificate_verifier(verifier)
+        .with_no_client_auth();
+
+    let name = ServerName::try_from(SERVER_NAME).unwrap();
+    let conn = ClientConnection::new(Arc::new(config), name).map_err(tls_error)?;
+
+    Ok(Connection::Client(conn))
+}
+
+fn tls_error(e: rustls::Error) -> Error {
+    Error::new(InvalidData, format!("tls: {}", e))
+}
+
+/* accepts the pinned certificate, or any certificate if nothing is pinned yet */
+struct PinnedServer {
+    pinned: Option<String>,
+    seen: Mutex<Option<String>>,
+}
+
+impl ServerCertVerifier for PinnedServer {
+    fn verify_server_cert(
+        &self,
+        end_entity: &Certificate,
+        _intermediates: &[Certificate],
+        _server_name: &ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        _now: SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let seen = fingerprint(&end_entity.0);
+        *self.seen.lock().unwrap() = Some(seen.clone());
+
+        match &self.pinned {
+            Some(pinned) if *pinned != seen => Err(rustls::Error::General(
+                "server certificate changed".to_string(),
+            )),
+            _ => Ok(ServerCertVerified::assertion()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fingerprint_is_colon_separated_sha256() {
+        assert_eq!(
+            fingerprint(b""),
+            "SHA256:e3:b0:c4:42:98:fc:1c:14:9a:fb:f4:c8:99:6f:b9:24:\
+             27:ae:41:e4:64:9b:93:4c:a4:95:99:1b:78:52:b8:55"
+        );
+    }
+
+    #[test]
+    fn fingerprint_tells_certificates_apart() {
+        assert_ne!(fingerprint(b"one"), fingerprint(b"two"));
+        assert_eq!(fingerprint(b"one"), fingerprint(b"one"));
+    }
+
+    #[test]
+    fn only_a_missing_store_pins_nothing() {
+        let dir = std::env::temp_dir().join(format!("transistor-known-{}", std::process::id()));
+        let file = dir.join("known_servers.json");
+        fs::create_dir_all(&dir).unwrap();
+
+        let _ = fs::remove_file(&file);
+        assert!(load_known_servers(&file).unwrap().is_empty());
+
+        write_atomic(&file, b"{ \"10.0.0.1:2426\": \"SHA256:00\" }").unwrap();
+        assert_eq!(load_known_servers(&file).unwrap().len(), 1);
+
+        // cut off halfway
+        fs::write(&file, "{ \"10.0.0.1:2426\": ").unwrap();
+        assert_eq!(load_known_servers(&file).unwrap_err().kind(), InvalidData);
+
+        // there but not readable as a file
+        fs::remove_file(&file).unwrap();
+        fs::create_dir(&file).unwrap();
+        assert!(load_known_servers(&file).is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,56 +1,158 @@
+use std::fmt;
+use std::fs;
 use std::io::{stdin, Error, ErrorKind::*, Read, Write};
-use std::net::TcpStream;
+use std::path::Path;
 
-use display_info::DisplayInfo;
 use serde::{Deserialize, Serialize};
 
+use crate::comm::Challenge;
+use crate::display::*;
+use crate::tls::SecureStream;
+use crate::Cid;
+
+/* keep the order of the variants; a client of any version has to read a version refusal */
 #[derive(Serialize, Deserialize, Debug)]
 pub enum HandshakeStatus {
     HandshakeOk,
-    HandshakeErr,
+    HandshakeErr(String), // protocol version mismatch; stays a plain string in every version
+    HandshakeRejected(Rejection),
+    HandshakeChallenge(Challenge), // answer to every hello of this version, known cid or not
 }
 
-pub fn print_displays() {
-    println!("[INF] detected system displays:");
+/* why the server refused a client */
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum Rejection {
+    Unauthorized(Cid),
+    DuplicateCid(Cid),
+    Overlap(String),  // the overlapping displays
+    Isolated(String), // the displays the cursor cannot get to
+    ServerFull(usize),
+    Malformed(String),
+    PairingClosed,
+    WrongCode,
+    Declined, // by the operator of the server
+}
 
-    for display in DisplayInfo::all().unwrap() {
-        println!("  {:?}", display);
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Rejection::Unauthorized(cid) => write!(f, "client {} is not authorized", cid),
+            Rejection::DuplicateCid(cid) => {
+                write!(f, "another client with cid {} is connected", cid)
+            }
+            Rejection::Overlap(reason) => write!(f, "{}", reason),
+            Rejection::Isolated(reason) => write!(f, "{}", reason),
+            Rejection::ServerFull(max) => write!(f, "server is full ({} clients)", max),
+            Rejection::Malformed(reason) => write!(f, "malformed request: {}", reason),
+            Rejection::PairingClosed => write!(f, "server is not pairing"),
+            Rejection::WrongCode => write!(f, "wrong pairing code"),
+            Rejection::Declined => write!(f, "pairing declined on the server"),
+        }
+    }
+}
+
+pub fn print_displays(source: &dyn DisplaySource) {
+    println!("[INF] detected displays:");
+
+    match source.displays(0) {
+        Ok(displays) => {
+            for display in displays {
+                println!("  {:?}", display);
+            }
+        }
+        Err(e) => eprintln!("[ERR] {}", e),
     }
 
     println!();
 }
 
+/* `--displays <json file>` or `--virtual <WxH[+X+Y],...>`; system displays otherwise */
+pub fn display_source(args: &[String]) -> Result<Box<dyn DisplaySource>, Error> {
+    let value = |flag: &str| {
+        args.iter()
+            .position(|x| x == flag)
+            .map(|i| args.get(i + 1).cloned())
+    };
+
+    if let Some(file) = value("--displays") {
+        let file = file.ok_or(Error::new(InvalidInput, "no display file specified"))?;
+        return Ok(Box::new(JsonDisplays { file: file.into() }));
+    }
+
+    if let Some(spec) = value("--virtual") {
+        let spec = spec.ok_or(Error::new(InvalidInput, "no virtual display specified"))?;
+        return Ok(Box::new(VirtualDisplays::parse(&spec)?));
+    }
+
+    Ok(Box::new(SystemDisplays))
+}
+
+/* mouce and winit need a graphical session; fail instead of crashing on a headless box */
+pub fn check_graphical_session() -> Result<(), Error> {
+    #[cfg(target_os = "linux")]
+    if std::env::var_os("DISPLAY").is_none() && std::env::var_os("WAYLAND_DISPLAY").is_none() {
+        return Err(Error::new(NotConnected, "no X or Wayland display found"));
+    }
+
+    Ok(())
+}
+
+/* private keys; readable by the owner only from the moment the file exists */
+pub fn write_private(path: &Path, data: &[u8]) -> Result<(), Error> {
+    // a leftover file would keep whatever permissions it had
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != NotFound => return Err(e),
+        _ => {}
+    }
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    options.open(path)?.write_all(data)
+}
+
+/* replace the file in one step; a crash halfway through leaves the old content in place */
+pub fn write_atomic(path: &Path, data: &[u8]) -> Result<(), Error> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+
+    fs::write(&tmp, data)?;
+    fs::rename(&tmp, path)
+}
+
 pub fn stdin_i32() -> Result<i32, Error> {
     let mut input = String::new();
 
-    if let Err(_) = stdin().read_line(&mut input) {
+    if stdin().read_line(&mut input).is_err() {
         return Err(Error::new(InvalidInput, "stdin read failure"));
     };
 
     match input.trim().parse() {
         Ok(i) => Ok(i),
-        Err(_) => {
-            return Err(Error::new(InvalidInput, "invalid input"));
-        }
+        Err(_) => Err(Error::new(InvalidInput, "invalid input")),
     }
 }
 
 pub fn stdin_char() -> Result<char, Error> {
     let mut input = String::new();
 
-    if let Err(_) = stdin().read_line(&mut input) {
+    if stdin().read_line(&mut input).is_err() {
         return Err(Error::new(InvalidInput, "stdin read failure"));
     };
 
     match input.trim().parse() {
         Ok(i) => Ok(i),
-        Err(_) => {
-            return Err(Error::new(InvalidInput, "invalid input"));
-        }
+        Err(_) => Err(Error::new(InvalidInput, "invalid input")),
     }
 }
 
-pub fn tcp_read(stream: &mut TcpStream, buffer: &mut Vec<u8>) -> Result<usize, Error> {
+pub fn tcp_read(stream: &mut SecureStream, buffer: &mut Vec<u8>) -> Result<usize, Error> {
     let mut size = [0u8; 4];
     stream.read_exact(&mut size)?;
 
@@ -62,13 +164,51 @@ pub fn tcp_read(stream: &mut TcpStream, buffer: &mut Vec<u8>) -> Result<usize, E
     Ok(len)
 }
 
-pub fn tcp_write<T: Serialize>(stream: &mut TcpStream, data: T) -> Result<usize, Error> {
+/* like tcp_read, but Ok(None) if no frame starts within the read timeout of the stream */
+pub fn tcp_poll(stream: &mut SecureStream, buffer: &mut Vec<u8>) -> Result<Option<usize>, Error> {
+    let mut size = [0u8; 4];
+
+    match stream.read(&mut size[..1]) {
+        Ok(0) => return Err(Error::new(UnexpectedEof, "connection closed")),
+        Ok(_) => {}
+        Err(e) if matches!(e.kind(), WouldBlock | TimedOut) => return Ok(None),
+        Err(e) => return Err(e),
+    }
+
+    // the rest of the frame is on its way; a timeout now would tear it apart
+    read_whole(stream, &mut size[1..])?;
+
+    let len = u32::from_be_bytes(size) as usize;
+    buffer.resize(len, 0);
+
+    read_whole(stream, buffer)?;
+
+    Ok(Some(len))
+}
+
+fn read_whole(stream: &mut SecureStream, mut buffer: &mut [u8]) -> Result<(), Error> {
+    while !buffer.is_empty() {
+        match stream.read(buffer) {
+            Ok(0) => return Err(Error::new(UnexpectedEof, "connection closed")),
+            Ok(n) => buffer = &mut buffer[n..],
+            Err(e) if matches!(e.kind(), WouldBlock | TimedOut | Interrupted) => {}
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(())
+}
+
+pub fn tcp_write<T: Serialize>(stream: &mut SecureStream, data: T) -> Result<usize, Error> {
     let encoded = bincode::serialize(&data).unwrap();
     let len = encoded.len();
     let size = (len as u32).to_be_bytes(); // force 4 byte data length
 
-    stream.write_all(&size)?;
-    stream.write_all(&encoded)?;
+    // a single write keeps the frame in one record
+    let mut frame = size.to_vec();
+    frame.extend(encoded);
+
+    stream.write_all(&frame)?;
 
     Ok(len)
 }
@@ -86,3 +226,50 @@ macro_rules! config_dir {
     }};
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(unix)]
+    fn private_file_is_for_the_owner_only() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("transistor-key-{}", std::process::id()));
+        let file = dir.join("key.der");
+
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(&file, "stale").unwrap();
+        fs::set_permissions(&file, fs::Permissions::from_mode(0o644)).unwrap();
+
+        write_private(&file, b"secret").unwrap();
+
+        let mode = fs::metadata(&file).unwrap().permissions().mode();
+
+        assert_eq!(mode & 0o777, 0o600);
+        assert_eq!(fs::read(&file).unwrap(), b"secret");
+    }
+
+    #[test]
+    fn version_refusal_reads_the_same_in_every_version() {
+        let frame = bincode::serialize(&HandshakeStatus::HandshakeErr("v".to_string())).unwrap();
+
+        // variant 1, then a length-prefixed string
+        assert_eq!(frame, [1, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, b'v']);
+    }
+
+    #[test]
+    fn atomic_write_replaces_the_file_without_leftovers() {
+        let dir = std::env::temp_dir().join(format!("transistor-atomic-{}", std::process::id()));
+        let file = dir.join("store.json");
+        fs::create_dir_all(&dir).unwrap();
+
+        write_atomic(&file, b"old").unwrap();
+        write_atomic(&file, b"new").unwrap();
+
+        assert_eq!(fs::read(&file).unwrap(), b"new");
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,67 @@
+use transistor::*;
+
+// 1920x1080 client display attached left of the server, at (0, 0) on its own OS
+fn client_display() -> Display {
+    let mut disp = VirtualDisplays::parse("1920x1080")
+        .unwrap()
+        .displays(1)
+        .unwrap()
+        .remove(0);
+
+    disp.x = -1920;
+    disp.y = 0;
+    disp
+}
+
+#[test]
+fn roundtrip_between_spaces() {
+    let disp = client_display();
+
+    assert_eq!(disp.global_to_local(-1920, 0), (0, 0));
+    assert_eq!(disp.local_to_global(0, 0), (-1920, 0));
+    assert_eq!(disp.local_to_system(100, 200), (100, 200));
+    assert_eq!(disp.system_to_local(100, 200), (100, 200));
+    assert_eq!(disp.global_to_system(-1, 1079), (1919, 1079));
+    assert_eq!(disp.system_to_global(1919, 1079), (-1, 1079));
+}
+
+#[test]
+fn offset_system_origin() {
+    let mut disp = client_display();
+    disp.system_x = 2560;
+    disp.system_y = -300;
+
+    assert_eq!(disp.global_to_system(-1920, 0), (2560, -300));
+    assert_eq!(disp.system_to_global(2560 + 10, -300 + 20), (-1910, 20));
+}
+
+#[test]
+fn entering_from_the_right_edge_stays_on_the_display() {
+    let disp = client_display();
+
+    // cursor leaving the server display at its left edge
+    let (x, y) = disp.entry_point(ZoneDirection::HorizontalLeft, 0, 540);
+    assert!(disp.contains(x, y));
+
+    let (x, y) = disp.global_to_system(x, y);
+    assert!(x >= 0 && x < disp.width);
+    assert_eq!(y, 540);
+}
+
+#[test]
+fn portrait_display() {
+    let mut disp = client_display();
+    disp.rotation = 90.0;
+
+    assert_eq!(disp.size(), (1080, 1920));
+
+    // top left of the unrotated panel ends up at the top right of the screen
+    assert_eq!(disp.local_to_global(0, 0), (-1920 + 1079, 0));
+    assert_eq!(disp.global_to_system(-1920 + 1079, 0), (1079, 0));
+
+    for (x, y) in [(0, 0), (1919, 0), (0, 1079), (1919, 1079), (640, 360)] {
+        let (gx, gy) = disp.local_to_global(x, y);
+        assert!(disp.contains(gx, gy));
+        assert_eq!(disp.global_to_local(gx, gy), (x, y));
+    }
+}
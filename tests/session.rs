@@ -0,0 +1,205 @@
+// XDG_DATA_HOME keeps the real config dirs out of reach on linux only
+#![cfg(target_os = "linux")]
+
+use std::env;
+use std::fs;
+use std::io::Error;
+use std::net::TcpListener;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use ring::rand::SystemRandom;
+use ring::signature::{Ed25519KeyPair, KeyPair};
+use transistor::*;
+
+const CID: Cid = 7;
+
+/* RecordingSink the test can look into while the client owns it */
+#[derive(Clone, Default)]
+struct SharedSink(Arc<Mutex<RecordingSink>>);
+
+impl SharedSink {
+    fn events(&self) -> Vec<SinkEvent> {
+        self.0.lock().unwrap().events.clone()
+    }
+}
+
+impl InputSink for SharedSink {
+    fn position(&self) -> Result<(i32, i32), Error> {
+        self.0.lock().unwrap().position()
+    }
+
+    fn warp(&mut self, x: i32, y: i32) -> Result<(), Error> {
+        self.0.lock().unwrap().warp(x, y)
+    }
+
+    fn motion(&mut self, dx: i32, dy: i32) -> Result<(), Error> {
+        self.0.lock().unwrap().motion(dx, dy)
+    }
+
+    fn button(&mut self, button: Button, pressed: bool) -> Result<(), Error> {
+        self.0.lock().unwrap().button(button, pressed)
+    }
+
+    fn scroll(&mut self, delta: ScrollDelta) -> Result<(), Error> {
+        self.0.lock().unwrap().scroll(delta)
+    }
+
+    fn key(&mut self, input: KeyInput, pressed: bool) -> Result<(), Error> {
+        self.0.lock().unwrap().key(input, pressed)
+    }
+}
+
+/* fresh config dirs with the client already paired and placed right of the server;
+ * returns the authorized_clients.json of the server */
+fn setup(client: &VirtualDisplays) -> PathBuf {
+    let home = env::temp_dir().join(format!("transistor-session-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&home);
+    env::set_var("XDG_DATA_HOME", &home);
+
+    let server_dir = home.join("transistor").join("server");
+    let client_dir = home.join("transistor").join("client");
+    fs::create_dir_all(&server_dir).unwrap();
+    fs::create_dir_all(&client_dir).unwrap();
+
+    let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+    let key = Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap();
+
+    fs::write(client_dir.join("cid.txt"), CID.to_string()).unwrap();
+    fs::write(client_dir.join("client_key.der"), pkcs8.as_ref()).unwrap();
+
+    let authorized = vec![AuthorizedClient {
+        cid: CID,
+        key: Some(hex::encode(key.public_key())),
+    }];
+    fs::write(
+        server_dir.join("authorized_clients.json"),
+        serde_json::to_string(&authorized).unwrap(),
+    )
+    .unwrap();
+
+    let mut displays = client.displays(CID).unwrap();
+    displays[0].x = 1920;
+    displays[0].y = 0;
+    fs::write(
+        client_dir.join("client_config.json"),
+        serde_json::to_string(&displays).unwrap(),
+    )
+    .unwrap();
+
+    server_dir.join("authorized_clients.json")
+}
+
+/* polls until the recorded events satisfy `done` */
+fn wait_for(sink: &SharedSink, done: impl Fn(&[SinkEvent]) -> bool) -> bool {
+    let deadline = Instant::now() + Duration::from_secs(10);
+
+    while Instant::now() < deadline {
+        if done(&sink.events()) {
+            return true;
+        }
+
+        thread::sleep(Duration::from_millis(20));
+    }
+
+    false
+}
+
+fn key(key: Key) -> KeyInput {
+    KeyInput {
+        key,
+        scan: 0,
+        modifiers: Modifiers::default(),
+    }
+}
+
+#[test]
+fn input_follows_the_cursor_to_the_client() {
+    let server_displays = VirtualDisplays::parse("1920x1080").unwrap();
+    let client_displays = VirtualDisplays::parse("1920x1080").unwrap();
+
+    let authorized = setup(&client_displays);
+    let server = Server::new(&server_displays).unwrap();
+    let (input, mut source) = MockSource::live(960, 540);
+
+    // any free port; other tests and a running server keep theirs
+    let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+    let port = listener.local_addr().unwrap().port();
+
+    thread::spawn(move || server.serve(&mut source, authorized, listener));
+
+    let sink = SharedSink::default();
+    let mut client_sink = sink.clone();
+
+    thread::spawn(move || {
+        let deadline = Instant::now() + Duration::from_secs(10);
+
+        // the server may not listen yet
+        let mut client = loop {
+            match Client::new(&format!("127.0.0.1:{}", port), &client_displays) {
+                Ok(client) => break client,
+                Err(e) if Instant::now() > deadline => panic!("cannot connect: {}", e),
+                Err(_) => thread::sleep(Duration::from_millis(50)),
+            }
+        };
+
+        client.start_with(&mut client_sink)
+    });
+
+    // push through the right edge until the client is connected and the cursor crosses
+    let crossed = wait_for(&sink, |events| {
+        if events.iter().any(|x| matches!(x, SinkEvent::Warp(..))) {
+            return true;
+        }
+
+        input.send(InputEvent::Motion(8, 0)).unwrap();
+        input.send(InputEvent::Position(1919, 540)).unwrap();
+        thread::sleep(Duration::from_millis(50));
+        false
+    });
+    assert!(crossed, "cursor never reached the client");
+
+    let delta = ScrollDelta { x: 0.0, y: -1.5 };
+
+    input.send(InputEvent::Key(key(Key::A), true)).unwrap();
+    input.send(InputEvent::Key(key(Key::A), false)).unwrap();
+    input.send(InputEvent::Button(Button::Left, true)).unwrap();
+    input.send(InputEvent::Button(Button::Left, false)).unwrap();
+    input.send(InputEvent::Scroll(delta)).unwrap();
+
+    let expected = [
+        SinkEvent::Key(Key::A, true),
+        SinkEvent::Key(Key::A, false),
+        SinkEvent::Button(Button::Left, true),
+        SinkEvent::Button(Button::Left, false),
+        SinkEvent::Scroll(delta),
+    ];
+
+    let arrived = wait_for(&sink, |events| {
+        let rest: Vec<&SinkEvent> = events
+            .iter()
+            .filter(|x| !matches!(x, SinkEvent::Warp(..) | SinkEvent::Motion(..)))
+            .collect();
+
+        rest.len() >= expected.len()
+    });
+    assert!(arrived, "input never reached the client");
+
+    let events = sink.events();
+    let (x, y) = match events.iter().find(|x| matches!(x, SinkEvent::Warp(..))) {
+        Some(SinkEvent::Warp(x, y)) => (*x, *y),
+        _ => unreachable!(),
+    };
+
+    // entered at the left edge of the client display, at the height it left the server
+    assert!((0..MARGIN * 2 + 1).contains(&x), "entered at x = {}", x);
+    assert_eq!(y, 540);
+
+    let rest: Vec<SinkEvent> = events
+        .into_iter()
+        .filter(|x| !matches!(x, SinkEvent::Warp(..) | SinkEvent::Motion(..)))
+        .collect();
+    assert_eq!(rest, expected);
+}